@@ -0,0 +1,108 @@
+// kildin admin create [--state <0-2>] [--scopes <a,b>] [--label <label>] [--owner <owner>] [--expires-at <unix time>] [--quota <n>]
+// kildin admin list
+// kildin admin revoke <key id>
+
+// crate
+use crate::database::managers::{ManagerAuth, ManagerState, Scope, TokenOptions, TOKEN_LENGTH};
+use crate::database::pool::Pool;
+use crate::helpers::random::random_string;
+use crate::helpers::types;
+use crate::commands::CLI_KEY_ID;
+
+// clap
+use clap::ArgMatches;
+
+fn parse_flag<T: std::str::FromStr>(args: &ArgMatches, name: &str) -> Result<Option<T>, types::AnyError> {
+    match args.value_of(name) {
+        Some(value) => match value.parse::<T>() {
+            Ok(value) => Ok(Some(value)),
+            Err(_) => Err(format!("Invalid value for --{}: {}", name, value).into())
+        },
+        None => Ok(None)
+    }
+}
+
+fn create(pool: &Pool, args: &ArgMatches) -> Result<(), types::AnyError> {
+    let state: u8 = parse_flag(args, "state")?.unwrap_or(2);
+
+    let scopes = match args.value_of("scopes") {
+        Some(scopes) => {
+            let mut vec = Vec::new();
+
+            for s in scopes.split(',').map(|s| s.trim()).filter(|s| s.len() != 0) {
+                match Scope::parse(s) {
+                    Some(scope) => vec.push(scope),
+                    None => return Err(format!("Unknown scope: {}", s).into())
+                }
+            }
+
+            Some(vec)
+        },
+        None => None
+    };
+
+    let options = TokenOptions {
+        label: args.value_of("label").map(|s| s.to_string()),
+        owner: args.value_of("owner").map(|s| s.to_string()),
+        expires_at: parse_flag(args, "expires-at")?,
+        quota: parse_flag(args, "quota")?
+    };
+
+    let mut manager = pool.managers()?;
+    let token = random_string(TOKEN_LENGTH);
+    let state: ManagerState = state.into();
+
+    if state == ManagerState::Unknown {
+        return Err("The state must be 0 (disabled), 1 (ok) or 2 (admin)".into());
+    }
+
+    manager.add_token(&token, state, scopes.as_deref(), &options)?;
+
+    let key_id = ManagerAuth::key_id(&token);
+    pool.audit()?.record(CLI_KEY_ID, "managers.add", &key_id, &format!("state {:?}", state))?;
+
+    println!("Key id: {}", key_id);
+    println!("Token: {}", token);
+    println!("The token is only shown once, store it somewhere safe.");
+    Ok(())
+}
+
+fn list(pool: &Pool) -> Result<(), types::AnyError> {
+    let managers = pool.managers()?.list()?;
+    let or_dash = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or("-".into());
+
+    println!("{:<10} {:<6} {:<16} {:<16} {:<12} {:<12} {:<12} {:<8} {}",
+        "KEY ID", "STATE", "LABEL", "OWNER", "CREATED", "EXPIRES", "LAST USED", "QUOTA", "SCOPES");
+
+    for m in managers {
+        println!("{:<10} {:<6} {:<16} {:<16} {:<12} {:<12} {:<12} {:<8} {}",
+            m.key_id, m.state,
+            m.label.as_deref().unwrap_or("-"), m.owner.as_deref().unwrap_or("-"),
+            or_dash(m.created_at), or_dash(m.expires_at), or_dash(m.last_used_at),
+            or_dash(m.quota.map(|q| q as u64)), m.scopes.join(" "));
+    }
+
+    Ok(())
+}
+
+fn revoke(pool: &Pool, args: &ArgMatches) -> Result<(), types::AnyError> {
+    let key_id = ManagerAuth::key_id(args.value_of("id").unwrap_or_default());
+
+    if !pool.managers()?.delete(&key_id)? {
+        return Err(format!("No manager with the key id {}", key_id).into());
+    }
+
+    pool.audit()?.record(CLI_KEY_ID, "managers.delete", &key_id, "revoked")?;
+    println!("Revoked {}.", key_id);
+    Ok(())
+}
+
+// `args` are the matches of the admin subcommand
+pub fn run(pool: &Pool, args: &ArgMatches) -> Result<(), types::AnyError> {
+    match args.subcommand() {
+        ("create", Some(args)) => create(pool, args),
+        ("list", _) => list(pool),
+        ("revoke", Some(args)) => revoke(pool, args),
+        _ => Err(args.usage().into())
+    }
+}
@@ -0,0 +1,154 @@
+// kildin [--config <file>] [--database <file>] [--set <section.key=value>...] <command>
+//
+// commands:
+// - serve                  start the api and the proxy checker (default)
+// - check                  check the proxies that are due and print the results
+// - import <file>          add the proxies of a file, one "schema://address:port" per line
+// - export [file]          write the live proxies in the same format, to stdout by default
+// - db migrate             apply pending schema migrations
+// - config validate        check the configuration and exit
+// - admin create|list|revoke
+
+// clap
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand, crate_version};
+
+// once_cell
+use once_cell::sync::OnceCell;
+
+// std
+use std::env::args;
+
+const COMMANDS: [&str; 8] = ["serve", "check", "import", "export", "db", "config", "admin", "help"];
+
+static OPTIONS: OnceCell<Options> = OnceCell::new();
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+#[derive(Default)]
+pub struct Options {
+    pub config_path: Option<String>,
+    pub database_path: Option<String>,
+    pub overrides: Vec<(String, String)> // ("section.key", value)
+}
+
+// the global options, defaults if the command line hasn't been parsed yet
+pub fn options() -> &'static Options {
+    OPTIONS.get_or_init(Options::default)
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("kildin")
+        .version(crate_version!())
+        .about("A proxy rotator microservice")
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::with_name("config").long("config").short("c")
+            .takes_value(true).global(true).help("The config file, config.toml by default"))
+        .arg(Arg::with_name("database").long("database").short("d")
+            .takes_value(true).global(true).help("Overrides the database path of the config"))
+        .arg(Arg::with_name("set").long("set").short("s")
+            .takes_value(true).multiple(true).number_of_values(1).global(true)
+            .help("Overrides a config value, e.g. --set server.port=9000"))
+        .subcommand(SubCommand::with_name("serve")
+            .about("Starts the api and the proxy checker"))
+        .subcommand(SubCommand::with_name("check")
+            .about("Checks the proxies that are due and prints the results"))
+        .subcommand(SubCommand::with_name("import")
+            .about("Adds the proxies of a file, one schema://address:port per line")
+            .arg(Arg::with_name("file").required(true)))
+        .subcommand(SubCommand::with_name("export")
+            .about("Writes the live proxies, one schema://address:port per line")
+            .arg(Arg::with_name("file").help("Writes to stdout if omitted")))
+        .subcommand(SubCommand::with_name("db")
+            .about("Database maintenance")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("migrate")
+                .about("Applies pending schema migrations")))
+        .subcommand(SubCommand::with_name("config")
+            .about("Configuration maintenance")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("validate")
+                .about("Checks the configuration and exits")))
+        .subcommand(SubCommand::with_name("admin")
+            .about("Manages the api tokens")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("create")
+                .about("Creates a token and prints it once")
+                .arg(Arg::with_name("state").long("state").takes_value(true)
+                    .possible_values(&["0", "1", "2"]).default_value("2"))
+                .arg(Arg::with_name("scopes").long("scopes").takes_value(true)
+                    .help("Comma separated, e.g. proxies:read,ratelimits:write"))
+                .arg(Arg::with_name("label").long("label").takes_value(true))
+                .arg(Arg::with_name("owner").long("owner").takes_value(true))
+                .arg(Arg::with_name("expires-at").long("expires-at").takes_value(true)
+                    .help("Unix timestamp after which the token stops working"))
+                .arg(Arg::with_name("quota").long("quota").takes_value(true)
+                    .help("Requests per minute, 0 for unlimited")))
+            .subcommand(SubCommand::with_name("list")
+                .about("Lists every token without its secret"))
+            .subcommand(SubCommand::with_name("revoke")
+                .about("Deletes a token")
+                .arg(Arg::with_name("id").required(true).help("The key id or the whole token"))))
+}
+
+// `kildin config.toml [command]` used to be the only way to run kildin
+fn legacy_args(mut args: Vec<String>) -> Vec<String> {
+    let legacy = args.len() > 1
+        && !args[1].starts_with('-')
+        && !COMMANDS.contains(&args[1].as_str());
+
+    if legacy {
+        args.insert(1, "--config".into());
+
+        if args.len() == 3 {
+            args.push("serve".into());
+        }
+    }
+
+    args
+}
+
+// global args can be passed before or after the subcommand, the last one wins
+fn global<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    globals(matches, name).pop()
+}
+
+fn globals<'a>(matches: &'a ArgMatches, name: &str) -> Vec<&'a str> {
+    let mut values = Vec::new();
+    let mut current = Some(matches);
+
+    while let Some(m) = current {
+        if let Some(v) = m.values_of(name) {
+            for value in v {
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+        }
+
+        current = m.subcommand().1;
+    }
+
+    values
+}
+
+// parses the command line and stores the global options
+pub fn parse() -> ArgMatches<'static> {
+    let matches = app().get_matches_from(legacy_args(args().collect()));
+
+    let overrides = globals(&matches, "set").iter()
+        .map(|s| {
+            let mut parts = s.splitn(2, '=');
+            let key = parts.next().unwrap_or_default().trim().to_string();
+            (key, parts.next().unwrap_or_default().trim().to_string())
+        })
+        .collect();
+
+    let options = Options {
+        config_path: global(&matches, "config").map(|c| c.into()),
+        database_path: global(&matches, "database").map(|d| d.into()),
+        overrides
+    };
+
+    let _ = OPTIONS.set(options);
+    matches
+}
@@ -0,0 +1,126 @@
+// crate
+use crate::database::proxies::Proxy;
+use crate::helpers::logger::Logger;
+use crate::helpers::types;
+use crate::proxy_checker::ProxyChecker;
+use crate::database::index::ProxyIndex;
+use crate::database::pool::Pool;
+use crate::{setup_db, try_load_config};
+
+// clap
+use clap::ArgMatches;
+
+// std
+use std::fs;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+// key id used in the audit log for actions taken from the command line
+pub const CLI_KEY_ID: &str = "cli";
+
+pub fn check() -> Result<(), types::AnyError> {
+    let config = try_load_config()?;
+    let pool = setup_db(&config)?;
+
+    let logger = Logger::open(&config.logging)?;
+
+    let index = Arc::new(ProxyIndex::load(&pool, logger.clone())?);
+    let mut pc = ProxyChecker::new(&pool, index.clone(), config.proxy_settings.shared(), logger);
+    let mut proxies = pc.update()?;
+    proxies.extend(pc.resurrect()?);
+    pc.prune_history()?;
+    index.flush()?;
+
+    for p in proxies.iter() {
+        let state = if p.blacklisted { "blacklisted" } else { "live" };
+        println!("{:<40} rating {:>5.2}  fails {:>3}  {}", p.url(), p.rating, p.fails, state);
+    }
+
+    println!("Checked {} proxies.", proxies.len());
+    Ok(())
+}
+
+pub fn import(args: &ArgMatches) -> Result<(), types::AnyError> {
+    let file = args.value_of("file").unwrap_or_default();
+    let contents = fs::read_to_string(file)?;
+    let pool = setup_db(&try_load_config()?)?;
+
+    let mut skipped = 0;
+    let mut proxies = Vec::new();
+
+    for line in contents.lines().map(|l| l.trim()).filter(|l| l.len() != 0 && !l.starts_with('#')) {
+        match Proxy::parse(line) {
+            Some(proxy) => proxies.push(proxy),
+            None => {
+                eprintln!("Skipping invalid proxy: {}", line);
+                skipped += 1;
+            }
+        }
+    }
+
+    let amount = proxies.len();
+    pool.proxies()?.insert_proxies(proxies)?;
+    pool.audit()?.record(CLI_KEY_ID, "proxies.add", "proxies", &format!("{} proxies from {}", amount, file))?;
+
+    println!("Imported {} proxies, skipped {}.", amount, skipped);
+    Ok(())
+}
+
+pub fn export(args: &ArgMatches) -> Result<(), types::AnyError> {
+    let pool = setup_db(&try_load_config()?)?;
+    let mut proxies = pool.proxies()?;
+    let mut out: Box<dyn Write> = match args.value_of("file") {
+        Some(file) => Box::new(fs::File::create(file)?),
+        None => Box::new(io::stdout())
+    };
+
+    let mut idx = 0;
+
+    loop {
+        let entries = proxies.after(idx, 1000)?;
+
+        if entries.len() == 0 {
+            break;
+        }
+
+        idx = entries[entries.len() - 1].0;
+
+        for (_, proxy) in entries {
+            writeln!(out, "{}", proxy.url())?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn db(args: &ArgMatches) -> Result<(), types::AnyError> {
+    match args.subcommand() {
+        ("migrate", _) => {
+            let pool = Pool::open(&try_load_config()?.general)?;
+
+            for migration in pool.migrate()? {
+                println!("Applied {}", migration);
+            }
+
+            println!("The {} database is up to date.", pool.backend());
+            Ok(())
+        },
+        _ => Err(args.usage().into())
+    }
+}
+
+pub fn config(args: &ArgMatches) -> Result<(), types::AnyError> {
+    match args.subcommand() {
+        ("validate", _) => {
+            let config = try_load_config()?;
+
+            for warning in config.warnings.iter() {
+                eprintln!("Warning: {}", warning);
+            }
+
+            println!("The configuration is valid.");
+            Ok(())
+        },
+        _ => Err(args.usage().into())
+    }
+}
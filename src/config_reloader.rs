@@ -0,0 +1,85 @@
+// crate
+use crate::helpers::config::SharedSettings;
+use crate::helpers::{logger::{Level, Logger}, types};
+use crate::{cli, try_load_config};
+
+// std
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+// how often the config file is checked for changes
+const POLL_INTERVAL: u64 = 2;
+
+// Reloads the proxy checker settings when the config file changes or when asked to.
+// Only [proxy-checker-settings] is swapped, the other sections need a restart.
+pub struct ConfigReloader {
+    settings: SharedSettings,
+    modified: Mutex<Option<SystemTime>>,
+    logger: Logger
+}
+
+impl ConfigReloader {
+    pub fn new(settings: SharedSettings, logger: Logger) -> Self {
+        let modified = Mutex::new(Self::modified());
+        Self { settings, modified, logger }
+    }
+
+    fn path() -> String {
+        let options = cli::options();
+        options.config_path.clone().unwrap_or(cli::DEFAULT_CONFIG_PATH.into())
+    }
+
+    fn modified() -> Option<SystemTime> {
+        fs::metadata(Self::path()).and_then(|m| m.modified()).ok()
+    }
+
+    // loads the config again and swaps the settings, an invalid config keeps the old ones
+    pub fn reload(&self) -> Result<(), types::AnyError> {
+        let config = match try_load_config() {
+            Ok(config) => config,
+            Err(why) => {
+                self.logger.log_with(Level::Error, "ConfigReloader: Keeping the old settings",
+                    &[("error", &why)]);
+                return Err(why);
+            }
+        };
+
+        for warning in config.warnings.iter() {
+            self.logger.log_with(Level::Warn, "ConfigReloader: Check your configuration.", &[("warning", warning)]);
+        }
+
+        let mut settings = self.settings.write().map_err(|_| "Settings lock is poisoned")?;
+        *settings = config.proxy_settings;
+
+        self.logger.log(Level::Info, "ConfigReloader: Successfully reloaded the proxy checker settings!");
+        Ok(())
+    }
+
+    // reloads if the config file was modified since the last check
+    pub fn poll(&self) -> Result<bool, types::AnyError> {
+        let modified = Self::modified();
+        let mut last = self.modified.lock().map_err(|_| "Reloader lock is poisoned")?;
+
+        if modified.is_none() || *last == modified {
+            return Ok(false);
+        }
+
+        *last = modified;
+        drop(last);
+
+        self.reload()?;
+        Ok(true)
+    }
+
+    // polls the config file on a background thread
+    pub fn watch(reloader: Arc<Self>) {
+        thread::spawn(move || {
+            loop {
+                thread::sleep(Duration::from_secs(POLL_INTERVAL));
+                let _ = reloader.poll(); // errors are logged by reload
+            }
+        });
+    }
+}
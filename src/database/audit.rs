@@ -0,0 +1,115 @@
+// - audit_log [index, key id, timestamp, action, target, summary]
+//
+// rows can only be inserted, triggers abort every update and delete
+
+// rusqlite
+use rusqlite::params;
+
+// crate
+use crate::helpers::types;
+use crate::database::pool::{SqlitePool, Connection};
+
+// serde
+use serde_derive::Serialize;
+
+// std
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize)]
+pub struct AuditEntry {
+    pub id: u32,
+    pub key_id: String,
+    pub timestamp: u64,
+    pub action: String,
+    pub target: String,
+    pub summary: String
+}
+
+#[derive(Default)]
+pub struct AuditFilter {
+    pub key_id: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub since: Option<u64>,
+    pub until: Option<u64>
+}
+
+// implemented by every storage backend, see `Pool::audit`
+pub trait AuditStore: Send {
+    fn record(&mut self, key_id: &str, action: &str, target: &str, summary: &str)
+        -> Result<(), types::AnyError>;
+
+    // newest entries first
+    fn query(&mut self, filter: &AuditFilter, page: u32, per_page: u32)
+        -> Result<Vec<AuditEntry>, types::AnyError>;
+}
+
+pub(crate) fn now() -> u64 {
+    let start = SystemTime::now();
+    let dur = start
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    dur.as_secs()
+}
+
+// the sqlite backend
+pub struct AuditLog {
+    conn: Connection
+}
+
+impl AuditLog {
+    pub fn new(pool: &SqlitePool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+}
+
+impl AuditStore for AuditLog {
+    fn record(&mut self, key_id: &str, action: &str, target: &str, summary: &str)
+        -> Result<(), types::AnyError>
+    {
+        let query = "INSERT INTO audit_log (key_id, timestamp, action, target, summary) VALUES (?1, ?2, ?3, ?4, ?5)";
+        self.conn.execute(query, params![key_id, now() as i64, action, target, summary])?;
+        Ok(())
+    }
+
+    fn query(&mut self, filter: &AuditFilter, page: u32, per_page: u32)
+        -> Result<Vec<AuditEntry>, types::AnyError>
+    {
+        let query = "
+            SELECT id, key_id, timestamp, action, target, summary FROM audit_log
+            WHERE (?1 IS NULL OR key_id = ?1)
+                AND (?2 IS NULL OR action = ?2)
+                AND (?3 IS NULL OR target = ?3)
+                AND (?4 IS NULL OR timestamp >= ?4)
+                AND (?5 IS NULL OR timestamp <= ?5)
+            ORDER BY id DESC LIMIT ?6 OFFSET ?7
+        ";
+
+        let since = filter.since.map(|t| t as i64);
+        let until = filter.until.map(|t| t as i64);
+        let offset = page as i64 * per_page as i64;
+        let params = params![filter.key_id, filter.action, filter.target, since, until, per_page, offset];
+
+        let mut vec = Vec::new();
+        let mut stmt = self.conn.prepare(query)?;
+        let rows: _ = stmt.query_map(params, |row| {
+            let timestamp: i64 = row.get(2)?;
+
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                key_id: row.get(1)?,
+                timestamp: timestamp as u64,
+                action: row.get(3)?,
+                target: row.get(4)?,
+                summary: row.get(5)?
+            })
+        })?;
+
+        for row in rows {
+            vec.push(row?);
+        }
+
+        Ok(vec)
+    }
+}
@@ -0,0 +1,253 @@
+// - proxy_rules [website, kind /0 = block, 1 = allow/, target]
+//
+// target format:
+// - "address:port" matches a single proxy
+// - "address" matches every proxy on that address (exit ip or hostname)
+// - "address/bits" matches every proxy inside of the subnet
+
+// rusqlite
+use rusqlite::params;
+
+// crate
+use crate::helpers::types;
+use crate::database::proxies::Proxy;
+use crate::database::pool::{SqlitePool, Connection};
+
+// serde
+use serde_derive::{Serialize, Deserialize};
+
+// std
+use std::net::{IpAddr, SocketAddr};
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListKind {
+    Block, // 0
+    Allow, // 1
+}
+
+impl ListKind {
+    pub(crate) fn into_u8(self) -> u8 {
+        match self {
+            ListKind::Block => 0,
+            ListKind::Allow => 1
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            1 => ListKind::Allow,
+            _ => ListKind::Block
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct ListEntry {
+    pub website: String,
+    pub kind: ListKind,
+    pub target: String
+}
+
+pub enum Target {
+    Proxy(String, u16),
+    Address(String),
+    Subnet(IpAddr, u8)
+}
+
+impl Target {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+
+        if text.len() == 0 {
+            return None;
+        }
+
+        // subnet
+        if text.contains('/') {
+            let parts = text.split('/').collect::<Vec<&str>>();
+
+            if parts.len() != 2 {
+                return None;
+            }
+
+            let ip = parts[0].parse::<IpAddr>().ok()?;
+            let bits = parts[1].parse::<u8>().ok()?;
+            let max = if ip.is_ipv4() { 32 } else { 128 };
+
+            return if bits <= max { Some(Target::Subnet(ip, bits)) } else { None };
+        }
+
+        // bare ip address (also covers ipv6 addresses without a port)
+        if let Ok(ip) = text.parse::<IpAddr>() {
+            return Some(Target::Address(ip.to_string()));
+        }
+
+        // ip address with a port
+        if let Ok(addr) = text.parse::<SocketAddr>() {
+            return Some(Target::Proxy(addr.ip().to_string(), addr.port()));
+        }
+
+        // hostname, with or without a port
+        let parts = text.split(':').collect::<Vec<&str>>();
+
+        if !Self::is_hostname(parts[0]) {
+            return None;
+        }
+
+        match parts.len() {
+            1 => Some(Target::Address(parts[0].into())),
+            2 => {
+                let port = parts[1].parse::<u16>().ok()?;
+                Some(Target::Proxy(parts[0].into(), port))
+            },
+            _ => None
+        }
+    }
+
+    // dot separated labels of ascii letters, digits and '-'
+    fn is_hostname(text: &str) -> bool {
+        text.split('.').all(|label| label.len() != 0 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+    }
+
+    fn same_address(a: &str, b: &str) -> bool {
+        match (a.parse::<IpAddr>(), b.parse::<IpAddr>()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => a.eq_ignore_ascii_case(b)
+        }
+    }
+
+    fn in_subnet(net: &IpAddr, bits: u8, address: &str) -> bool {
+        let ip = match address.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return false
+        };
+
+        match (net, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = if bits == 0 { 0 } else { u32::MAX << (32 - bits as u32) };
+                (u32::from(*net) & mask) == (u32::from(ip) & mask)
+            },
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = if bits == 0 { 0 } else { u128::MAX << (128 - bits as u32) };
+                (u128::from(*net) & mask) == (u128::from(ip) & mask)
+            },
+            _ => false
+        }
+    }
+
+    pub fn matches(&self, proxy: &Proxy) -> bool {
+        match self {
+            Target::Proxy(address, port) =>
+                *port == proxy.port && Self::same_address(address, &proxy.address),
+            Target::Address(address) => Self::same_address(address, &proxy.address),
+            Target::Subnet(net, bits) => Self::in_subnet(net, *bits, &proxy.address)
+        }
+    }
+}
+
+// implemented by every storage backend, see `Pool::blocklist`
+pub trait BlocklistStore: Send {
+    // rules that already exist are skipped
+    fn add(&mut self, entries: Vec<ListEntry>) -> Result<(), types::AnyError>;
+
+    fn remove(&mut self, entries: Vec<ListEntry>) -> Result<(), types::AnyError>;
+
+    // every rule, or only the rules of `website` (global rules included), oldest first
+    fn list(&mut self, website: Option<&str>) -> Result<Vec<ListEntry>, types::AnyError>;
+
+    // the parsed rules of `website`, global rules included
+    fn rules(&mut self, website: &str) -> Result<Rules, types::AnyError> {
+        let mut rules = Rules { blocked: Vec::new(), allowed: Vec::new() };
+
+        for rule in self.list(Some(website))? {
+            if let Some(target) = Target::parse(&rule.target) {
+                match rule.kind {
+                    ListKind::Block => rules.blocked.push(target),
+                    ListKind::Allow => rules.allowed.push(target)
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+}
+
+// the sqlite backend
+pub struct Blocklist {
+    conn: Connection
+}
+
+impl Blocklist {
+    pub fn new(pool: &SqlitePool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+
+    fn read_entry(row: &rusqlite::Row) -> rusqlite::Result<ListEntry> {
+        let website: String = row.get(0)?;
+        let kind: u8 = row.get(1)?;
+        let target: String = row.get(2)?;
+        Ok(ListEntry { website, kind: ListKind::from_u8(kind), target })
+    }
+}
+
+impl BlocklistStore for Blocklist {
+    fn add(&mut self, entries: Vec<ListEntry>) -> Result<(), types::AnyError> {
+        let trs = self.conn.transaction()?;
+
+        for e in entries.iter() {
+            let query = "INSERT OR IGNORE INTO proxy_rules (website, kind, target) VALUES (?1, ?2, ?3)";
+            trs.execute(query, params![e.website, e.kind.into_u8(), e.target])?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, entries: Vec<ListEntry>) -> Result<(), types::AnyError> {
+        let trs = self.conn.transaction()?;
+
+        for e in entries.iter() {
+            let query = "DELETE FROM proxy_rules WHERE website = ?1 AND kind = ?2 AND target = ?3";
+            trs.execute(query, params![e.website, e.kind.into_u8(), e.target])?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn list(&mut self, website: Option<&str>) -> Result<Vec<ListEntry>, types::AnyError> {
+        let query = match website {
+            Some(_) => "SELECT website, kind, target FROM proxy_rules WHERE website = ?1 OR website = '*' ORDER BY id ASC",
+            None => "SELECT website, kind, target FROM proxy_rules ORDER BY id ASC"
+        };
+
+        let mut vec = Vec::new();
+        let mut stmt = self.conn.prepare(query)?;
+
+        let rows: _ = match website {
+            Some(w) => stmt.query_map(params![w], Self::read_entry)?.collect::<Vec<_>>(),
+            None => stmt.query_map(rusqlite::NO_PARAMS, Self::read_entry)?.collect::<Vec<_>>()
+        };
+
+        for row in rows {
+            vec.push(row?);
+        }
+
+        Ok(vec)
+    }
+}
+
+pub struct Rules {
+    blocked: Vec<Target>,
+    allowed: Vec<Target>
+}
+
+impl Rules {
+    // Not blocked, and if the website has an allowlist, on it.
+    pub fn permits(&self, proxy: &Proxy) -> bool {
+        !self.blocked.iter().any(|t| t.matches(proxy))
+            && (self.allowed.len() == 0 || self.allowed.iter().any(|t| t.matches(proxy)))
+    }
+}
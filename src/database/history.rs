@@ -0,0 +1,215 @@
+// tables:
+// - proxy_checks [index, proxy id, timestamp, target, latency_ms, status, error]
+// - proxy_check_rollups [proxy id, period, start, probes, passes, fails, target_down, latency_sum_ms, latency_samples]
+//
+// every probe of the proxy checker is kept for `history-retention`, and counted in
+// an hourly and a daily rollup that are kept for HOURLY_RETENTION and DAILY_RETENTION.
+
+// rusqlite
+use rusqlite::params;
+
+// crate
+use crate::helpers::types;
+use crate::database::pool::{SqlitePool, Connection};
+
+// serde
+use serde_derive::Serialize;
+
+pub const HOUR: u64 = 3600;
+pub const DAY: u64 = 86400;
+
+// secs the rollups are kept for
+pub const HOURLY_RETENTION: u64 = 30 * DAY;
+pub const DAILY_RETENTION: u64 = 365 * DAY;
+
+// the error class of a probe whose target couldn't be reached without the proxy either
+pub const TARGET_DOWN: &str = "target-down";
+
+// one request of the proxy checker to a target through a proxy
+#[derive(Clone, Serialize)]
+pub struct ProbeRecord {
+    #[serde(skip_serializing)]
+    pub proxy_id: u32,
+
+    pub timestamp: u64,
+    pub target: String,
+    pub latency_ms: Option<u32>, // until the response, none without one
+    pub status: Option<u16>,
+    pub error: Option<String>, // none if the probe passed, see `ProxyChecker` for the classes
+}
+
+impl ProbeRecord {
+    // (passes, fails, target_down) this probe adds to a rollup
+    pub(crate) fn counts(&self) -> (u32, u32, u32) {
+        match self.error.as_deref() {
+            None => (1, 0, 0),
+            Some(TARGET_DOWN) => (0, 0, 1),
+            Some(_) => (0, 1, 0)
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Rollup {
+    pub start: u64,
+    pub probes: u32,
+    pub passes: u32,
+    pub fails: u32,
+    pub target_down: u32,
+    pub avg_latency_ms: Option<f64>,
+}
+
+impl Rollup {
+    pub(crate) fn new(start: i64, counts: [i64; 4], latency_sum_ms: i64, latency_samples: i64) -> Self {
+        let avg_latency_ms = match latency_samples {
+            0 => None,
+            n => Some(latency_sum_ms as f64 / n as f64)
+        };
+
+        Self {
+            start: start as u64,
+            probes: counts[0] as u32,
+            passes: counts[1] as u32,
+            fails: counts[2] as u32,
+            target_down: counts[3] as u32,
+            avg_latency_ms
+        }
+    }
+}
+
+// the start of the `period` that `timestamp` falls in
+pub fn bucket(timestamp: u64, period: u64) -> u64 {
+    timestamp - timestamp % period
+}
+
+// implemented by every storage backend, see `Pool::history`
+pub trait HistoryStore: Send {
+    // keeps the probes and adds them to their rollups
+    fn record(&mut self, probes: &[ProbeRecord]) -> Result<(), types::AnyError>;
+
+    // probes of a proxy at or after `since`, the newest first
+    fn probes(&mut self, proxy_id: u32, since: u64, limit: u32) -> Result<Vec<ProbeRecord>, types::AnyError>;
+
+    // rollups of a proxy over `period` (HOUR or DAY) covering `since` or later, the newest first
+    fn rollups(&mut self, proxy_id: u32, period: u64, since: u64, limit: u32) -> Result<Vec<Rollup>, types::AnyError>;
+
+    // deletes probes from before `probes_before` and rollups past their retention, returns how many rows
+    fn prune(&mut self, now: u64, probes_before: u64) -> Result<usize, types::AnyError>;
+}
+
+// the sqlite backend
+pub struct History {
+    conn: Connection
+}
+
+impl History {
+    pub fn new(pool: &SqlitePool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+}
+
+impl HistoryStore for History {
+    fn record(&mut self, probes: &[ProbeRecord]) -> Result<(), types::AnyError> {
+        let trs = self.conn.transaction()?;
+        let insert = "
+            INSERT INTO proxy_checks (proxy_id, timestamp, target, latency_ms, status, error)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ";
+        let rollup = "
+            INSERT INTO proxy_check_rollups
+            (proxy_id, period, start, probes, passes, fails, target_down, latency_sum_ms, latency_samples)
+            VALUES (?1, ?2, ?3, 1, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT (proxy_id, period, start) DO UPDATE SET
+                probes = probes + 1,
+                passes = passes + excluded.passes,
+                fails = fails + excluded.fails,
+                target_down = target_down + excluded.target_down,
+                latency_sum_ms = latency_sum_ms + excluded.latency_sum_ms,
+                latency_samples = latency_samples + excluded.latency_samples
+        ";
+
+        for p in probes.iter() {
+            trs.execute(insert, params![p.proxy_id, p.timestamp as i64, p.target, p.latency_ms, p.status, p.error])?;
+
+            let (passes, fails, target_down) = p.counts();
+            let latency_samples = if p.latency_ms.is_some() { 1 } else { 0 };
+
+            for period in [HOUR, DAY].iter() {
+                let start = bucket(p.timestamp, *period) as i64;
+                trs.execute(rollup, params![p.proxy_id, *period as i64, start, passes, fails, target_down,
+                    p.latency_ms.unwrap_or(0), latency_samples])?;
+            }
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn probes(&mut self, proxy_id: u32, since: u64, limit: u32) -> Result<Vec<ProbeRecord>, types::AnyError> {
+        let query = "
+            SELECT timestamp, target, latency_ms, status, error FROM proxy_checks
+            WHERE proxy_id = ?1 AND timestamp >= ?2
+            ORDER BY timestamp DESC, id DESC LIMIT ?3
+        ";
+
+        let mut vec = Vec::new();
+        let mut stmt = self.conn.prepare(query)?;
+        let rows = stmt.query_map(params![proxy_id, since as i64, limit], |row| {
+            let timestamp: i64 = row.get(0)?;
+
+            Ok(ProbeRecord {
+                proxy_id,
+                timestamp: timestamp as u64,
+                target: row.get(1)?,
+                latency_ms: row.get(2)?,
+                status: row.get(3)?,
+                error: row.get(4)?
+            })
+        })?;
+
+        for row in rows {
+            vec.push(row?);
+        }
+
+        Ok(vec)
+    }
+
+    fn rollups(&mut self, proxy_id: u32, period: u64, since: u64, limit: u32) -> Result<Vec<Rollup>, types::AnyError> {
+        let query = "
+            SELECT start, probes, passes, fails, target_down, latency_sum_ms, latency_samples
+            FROM proxy_check_rollups
+            WHERE proxy_id = ?1 AND period = ?2 AND start >= ?3
+            ORDER BY start DESC LIMIT ?4
+        ";
+
+        let mut vec = Vec::new();
+        let mut stmt = self.conn.prepare(query)?;
+        let params = params![proxy_id, period as i64, bucket(since, period) as i64, limit];
+        let rows = stmt.query_map(params, |row| {
+            let counts = [row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?];
+            Ok(Rollup::new(row.get(0)?, counts, row.get(5)?, row.get(6)?))
+        })?;
+
+        for row in rows {
+            vec.push(row?);
+        }
+
+        Ok(vec)
+    }
+
+    fn prune(&mut self, now: u64, probes_before: u64) -> Result<usize, types::AnyError> {
+        let trs = self.conn.transaction()?;
+        let mut deleted = trs.execute("DELETE FROM proxy_checks WHERE timestamp < ?1", params![probes_before as i64])?;
+
+        for (period, retention) in [(HOUR, HOURLY_RETENTION), (DAY, DAILY_RETENTION)].iter() {
+            let query = "DELETE FROM proxy_check_rollups WHERE period = ?1 AND start < ?2";
+            let before = now.saturating_sub(*retention) as i64;
+            deleted += trs.execute(query, params![*period as i64, before])?;
+        }
+
+        trs.commit()?;
+        Ok(deleted)
+    }
+}
+
@@ -0,0 +1,298 @@
+// in-memory index of the live proxies and the active rate limits, `get_proxy` is
+// served from here instead of querying the database on every request.
+//
+// check results and rate limit changes are applied to the index right away and
+// written to the database by a background thread (write-behind), writes queued
+// in the last FLUSH_INTERVAL are lost if the process is killed. new proxies are
+// written immediately and join the index once stored. the index is reloaded every RESYNC_INTERVAL to pick up
+// changes made by other processes, e.g. `kildin import`.
+
+// crate
+use crate::database::pool::Pool;
+use crate::database::proxies::Proxy;
+use crate::database::ratelimited::{Entries, RateLimitEntry};
+use crate::helpers::{logger::{Level, Logger}, types};
+
+// std
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// how often queued writes are persisted
+const FLUSH_INTERVAL: u64 = 1;
+
+// how often the index is reloaded from the database
+const RESYNC_INTERVAL: u64 = 60;
+
+// rows per query while loading
+const LOAD_PAGE: u32 = 1000;
+
+type Key = (String, u16); // address, port
+
+// a change that still has to be written to the database
+enum Write {
+    Proxies(Vec<Proxy>), // check results of live proxies
+    Blacklisted(Vec<Proxy>), // check results of blacklisted proxies
+    RateLimit(Entries),
+    Unratelimit(Entries)
+}
+
+#[derive(Default)]
+struct Snapshot {
+    live: Vec<Proxy>, // best rating first
+    ratelimited: HashMap<String, HashMap<Key, u64>> // website ("*" for every website) -> proxy -> until
+}
+
+impl Snapshot {
+    fn load(pool: &Pool) -> Result<Self, types::AnyError> {
+        let mut snapshot = Self::default();
+        let mut proxies = pool.proxies()?;
+        let mut idx = 0;
+
+        loop {
+            let entries = proxies.after(idx, LOAD_PAGE)?;
+
+            if entries.len() == 0 {
+                break;
+            }
+
+            idx = entries[entries.len() - 1].0;
+            snapshot.live.extend(entries.into_iter().map(|e| e.1));
+        }
+
+        let entries = pool.ratelimited()?
+            .read_ratelimited(LOAD_PAGE, &|entries| entries.into_iter().map(|e| e.1).collect())?;
+
+        snapshot.sort();
+        snapshot.ratelimit(&entries);
+        Ok(snapshot)
+    }
+
+    fn key(proxy: &Proxy) -> Key {
+        (proxy.address.clone(), proxy.port)
+    }
+
+    fn sort(&mut self) {
+        self.live.sort_by(|a, b| b.rating.partial_cmp(&a.rating).unwrap_or(Ordering::Equal));
+    }
+
+    // blacklisted proxies leave the index
+    fn update(&mut self, proxies: &[Proxy]) {
+        let updates = proxies.iter()
+            .map(|p| (Self::key(p), p))
+            .collect::<HashMap<Key, &Proxy>>();
+
+        for proxy in self.live.iter_mut() {
+            if let Some(update) = updates.get(&Self::key(proxy)) {
+                proxy.rating = update.rating;
+                proxy.fails = update.fails;
+                proxy.blacklisted = update.blacklisted;
+                proxy.next_check_at = update.next_check_at;
+                proxy.successes = update.successes;
+                proxy.blacklisted_at = update.blacklisted_at;
+            }
+        }
+
+        self.live.retain(|p| !p.blacklisted);
+        self.sort();
+    }
+
+    // proxies that aren't blacklisted join the index unless they're already in it, e.g.
+    // new ones or blacklisted proxies that passed enough checks
+    fn add(&mut self, proxies: &[Proxy]) {
+        let live = self.live.iter().map(Self::key).collect::<HashSet<Key>>();
+
+        for proxy in proxies.iter().filter(|p| !p.blacklisted && !live.contains(&Self::key(p))) {
+            self.live.push(proxy.clone());
+        }
+
+        self.sort();
+    }
+
+    // same as the database, entries that already exist are skipped
+    fn ratelimit(&mut self, entries: &[RateLimitEntry]) {
+        for e in entries {
+            self.ratelimited.entry(e.website.clone())
+                .or_insert_with(HashMap::new)
+                .entry((e.address.clone(), e.port))
+                .or_insert(e.until);
+        }
+    }
+
+    fn unratelimit(&mut self, entries: &[RateLimitEntry]) {
+        for e in entries {
+            if let Some(proxies) = self.ratelimited.get_mut(&e.website) {
+                proxies.remove(&(e.address.clone(), e.port));
+
+                if proxies.len() == 0 {
+                    self.ratelimited.remove(&e.website);
+                }
+            }
+        }
+    }
+
+    fn is_ratelimited(&self, website: &str, proxy: &Proxy, now: u64) -> bool {
+        let key = Self::key(proxy);
+
+        [website, "*"].iter().any(|w| {
+            self.ratelimited.get(*w)
+                .and_then(|proxies| proxies.get(&key))
+                .map_or(false, |until| *until > now)
+        })
+    }
+}
+
+pub struct ProxyIndex {
+    pool: Pool,
+    snapshot: RwLock<Snapshot>,
+    pending: Mutex<VecDeque<Write>>, // also held while writing, see `resync`
+    logger: Logger
+}
+
+impl ProxyIndex {
+    pub fn load(pool: &Pool, logger: Logger) -> Result<Self, types::AnyError> {
+        let snapshot = RwLock::new(Snapshot::load(pool)?);
+        Ok(Self { pool: pool.clone(), snapshot, pending: Mutex::new(VecDeque::new()), logger })
+    }
+
+    fn now() -> u64 {
+        let start = SystemTime::now();
+        let dur = start
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        dur.as_secs()
+    }
+
+    fn pending(&self) -> Result<MutexGuard<VecDeque<Write>>, types::AnyError> {
+        Ok(self.pending.lock().map_err(|_| "Index queue lock is poisoned")?)
+    }
+
+    // applies `f` to the index and queues `write`, together so a resync can't lose either
+    fn write<F: FnOnce(&mut Snapshot)>(&self, write: Write, f: F) -> Result<(), types::AnyError> {
+        let mut pending = self.pending()?;
+        f(&mut *self.snapshot.write().map_err(|_| "Index lock is poisoned")?);
+        pending.push_back(write);
+        Ok(())
+    }
+
+    pub fn live(&self) -> Result<usize, types::AnyError> {
+        Ok(self.snapshot.read().map_err(|_| "Index lock is poisoned")?.live.len())
+    }
+
+    // up to `amount` proxies that aren't rate limited on `website` and pass `permits`.
+    // the best proxies come first, or with `min_rating` the lowest rated ones above it.
+    pub fn select<F: Fn(&Proxy) -> bool>(&self, website: &str, amount: u32, min_rating: Option<f64>, permits: F)
+        -> Result<Vec<Proxy>, types::AnyError>
+    {
+        let snapshot = self.snapshot.read().map_err(|_| "Index lock is poisoned")?;
+        let now = Self::now();
+
+        let candidates: Box<dyn Iterator<Item = &Proxy>> = match min_rating {
+            Some(min) => Box::new(snapshot.live.iter().rev().skip_while(move |p| p.rating <= min)),
+            None => Box::new(snapshot.live.iter())
+        };
+
+        let proxies = candidates
+            .filter(|p| !snapshot.is_ratelimited(website, p, now) && permits(p))
+            .take(amount as usize)
+            .cloned()
+            .collect::<Vec<Proxy>>();
+
+        Ok(proxies)
+    }
+
+    // new proxies are persisted right away, only the ones the database didn't know
+    // (and possibly blacklisted) are added. writers wait so a resync can't miss them
+    pub fn insert_proxies(&self, proxies: Vec<Proxy>) -> Result<(), types::AnyError> {
+        let _pending = self.pending()?;
+        let inserted = self.pool.proxies()?.insert_proxies(proxies)?;
+        self.snapshot.write().map_err(|_| "Index lock is poisoned")?.add(&inserted);
+        Ok(())
+    }
+
+    pub fn update_proxies(&self, proxies: Vec<Proxy>) -> Result<(), types::AnyError> {
+        self.write(Write::Proxies(proxies.clone()), |s| s.update(&proxies))
+    }
+
+    pub fn update_blacklisted(&self, proxies: Vec<Proxy>) -> Result<(), types::AnyError> {
+        self.write(Write::Blacklisted(proxies.clone()), |s| s.add(&proxies))
+    }
+
+    pub fn add_ratelimited(&self, entries: Entries) -> Result<(), types::AnyError> {
+        self.write(Write::RateLimit(entries.clone()), |s| s.ratelimit(&entries))
+    }
+
+    pub fn remove_ratelimited(&self, entries: Entries) -> Result<(), types::AnyError> {
+        self.write(Write::Unratelimit(entries.clone()), |s| s.unratelimit(&entries))
+    }
+
+    // persists the queued writes in order, a write that fails stays queued with the ones after it
+    fn apply(pool: &Pool, pending: &mut VecDeque<Write>) -> Result<usize, types::AnyError> {
+        if pending.len() == 0 {
+            return Ok(0);
+        }
+
+        let mut proxies = pool.proxies()?;
+        let mut ratelimited = pool.ratelimited()?;
+        let mut applied = 0;
+
+        while let Some(write) = pending.pop_front() {
+            let res = match &write {
+                Write::Proxies(p) => proxies.update_proxies(p.clone()),
+                Write::Blacklisted(p) => proxies.update_blacklisted(p.clone()),
+                Write::RateLimit(e) => ratelimited.add(e.clone()),
+                Write::Unratelimit(e) => ratelimited.remove(e.clone())
+            };
+
+            if let Err(why) = res {
+                pending.push_front(write);
+                return Err(why);
+            }
+
+            applied += 1;
+        }
+
+        Ok(applied)
+    }
+
+    // returns how many writes were persisted
+    pub fn flush(&self) -> Result<usize, types::AnyError> {
+        let mut pending = self.pending()?;
+        Self::apply(&self.pool, &mut pending)
+    }
+
+    // flushes and reloads the index, writers wait so nothing lands in between
+    pub fn resync(&self) -> Result<(), types::AnyError> {
+        let mut pending = self.pending()?;
+        Self::apply(&self.pool, &mut pending)?;
+
+        let snapshot = Snapshot::load(&self.pool)?;
+        *self.snapshot.write().map_err(|_| "Index lock is poisoned")? = snapshot;
+        Ok(())
+    }
+
+    // flushes and resyncs on a background thread
+    pub fn write_behind(index: Arc<Self>) {
+        thread::spawn(move || {
+            let mut synced = Instant::now();
+
+            loop {
+                thread::sleep(Duration::from_secs(FLUSH_INTERVAL));
+
+                let res = if synced.elapsed() >= Duration::from_secs(RESYNC_INTERVAL) {
+                    synced = Instant::now();
+                    index.resync().map(|_| 0)
+                } else {
+                    index.flush()
+                };
+
+                if let Err(why) = res {
+                    index.logger.log_with(Level::Error, "ProxyIndex: Couldn't persist the queued writes, retrying.",
+                        &[("error", &why)]);
+                }
+            }
+        });
+    }
+}
@@ -1,24 +1,65 @@
 // token format:
-// alphanumeric 32 characters
+// alphanumeric 32 characters, the first 8 of which are the public key id
+//
+// only the key id and a salted sha256 hash of the token are stored. after a
+// rotation the previous hash stays valid until `old_valid_until`.
+//
+// scopes format:
+// space separated scope names, e.g. "proxies:read ratelimits:write"
+// managers without stored scopes get the defaults of their state, a stored
+// scope this version doesn't know makes authentication fail instead of being dropped
 
 // rusqlite
 use rusqlite::types::Value;
-use rusqlite::Connection;
+use rusqlite::Transaction;
 use rusqlite::params;
+use rusqlite::OptionalExtension;
 
 // std
 use std::cmp::PartialEq;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// sha2
+use sha2::{Digest, Sha256};
+
+// serde
+use serde_derive::Serialize;
 
 // crate
 use crate::helpers::types;
-use crate::connect_to_database;
+use crate::helpers::random::random_string;
+use crate::database::pool::{SqlitePool, Connection};
+
+pub const TOKEN_LENGTH: usize = 32;
+pub const KEY_ID_LENGTH: usize = 8;
 
 pub enum ManagerResult<T> {
     Ok(T),
-    Err(&'static str)
+    Err(ManagerError)
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ManagerError {
+    InvalidToken,
+    Expired,
+    UnknownScope, // a stored scope this version doesn't know
+    Storage // the credentials couldn't be queried
 }
 
-#[derive(PartialEq)]
+impl std::fmt::Display for ManagerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let msg = match self {
+            Self::InvalidToken => "Invalid token",
+            Self::Expired => "Token expired",
+            Self::UnknownScope => "Unknown scope",
+            Self::Storage => "Failed to query for token"
+        };
+        write!(f, "{}", msg)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum ManagerState {
     Disabled, // 0
     Ok, // 1
@@ -37,35 +78,346 @@ impl Into<ManagerState> for u8 {
     }
 }
 
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Scope {
+    ProxiesRead,
+    ProxiesWrite,
+    RatelimitsWrite,
+    RatelimitsGlobal,
+    ManagersAdmin,
+    StatsRead,
+    BlocklistWrite,
+    BlocklistGlobal
+}
+
+impl Scope {
+    pub const ALL: [Scope; 8] = [
+        Scope::ProxiesRead, Scope::ProxiesWrite,
+        Scope::RatelimitsWrite, Scope::RatelimitsGlobal,
+        Scope::ManagersAdmin, Scope::StatsRead,
+        Scope::BlocklistWrite, Scope::BlocklistGlobal
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ProxiesRead => "proxies:read",
+            Scope::ProxiesWrite => "proxies:write",
+            Scope::RatelimitsWrite => "ratelimits:write",
+            Scope::RatelimitsGlobal => "ratelimits:global",
+            Scope::ManagersAdmin => "managers:admin",
+            Scope::StatsRead => "stats:read",
+            Scope::BlocklistWrite => "blocklist:write",
+            Scope::BlocklistGlobal => "blocklist:global"
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        Self::ALL.iter()
+            .find(|s| s.as_str() == text)
+            .copied()
+    }
+
+    // scopes given to managers that don't have any stored
+    pub fn defaults(state: ManagerState) -> Vec<Self> {
+        match state {
+            ManagerState::Admin => Self::ALL.to_vec(),
+            ManagerState::Ok => vec![Scope::ProxiesRead, Scope::RatelimitsWrite],
+            _ => Vec::new()
+        }
+    }
+
+    pub fn join(scopes: &[Self]) -> String {
+        scopes.iter()
+            .map(|s| s.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
+    // fails on the first unknown scope
+    pub fn split(text: &str) -> Result<Vec<Self>, String> {
+        text.split_whitespace()
+            .map(|s| Self::parse(s).ok_or_else(|| s.to_string()))
+            .collect()
+    }
+}
+
+pub struct Manager {
+    pub key_id: String,
+    pub state: ManagerState,
+    pub scopes: Vec<Scope>,
+    pub quota: Option<u32>, // requests per minute, `None` uses the configured default
+    pub replaced: bool // authenticated with the token the last rotation replaced
+}
+
+#[derive(Default)]
+pub struct TokenOptions {
+    pub label: Option<String>,
+    pub owner: Option<String>,
+    pub expires_at: Option<u64>,
+    pub quota: Option<u32>
+}
+
+// everything about a manager except for its secret
+#[derive(Serialize)]
+pub struct ManagerInfo {
+    pub key_id: String,
+    pub state: u8,
+    pub scopes: Vec<String>,
+    pub label: Option<String>,
+    pub owner: Option<String>,
+    pub created_at: Option<u64>,
+    pub expires_at: Option<u64>,
+    pub last_used_at: Option<u64>,
+    pub quota: Option<u32>
+}
+
+impl ManagerInfo {
+    // `times` are created_at, expires_at and last_used_at
+    pub(crate) fn from_row(key_id: String, state: ManagerState, scopes: Option<String>,
+        label: Option<String>, owner: Option<String>, times: [Option<i64>; 3], quota: Option<u32>) -> Self
+    {
+        // stored names are listed as they are, unknown ones included
+        let scopes = match scopes {
+            Some(scopes) => scopes.split_whitespace().map(String::from).collect(),
+            None => Scope::defaults(state).iter().map(|s| s.as_str().to_string()).collect()
+        };
+
+        let [created_at, expires_at, last_used_at] = times;
+
+        ManagerInfo {
+            key_id,
+            state: ManagerAuth::into_i16(state),
+            scopes,
+            label, owner,
+            created_at: created_at.map(|t| t as u64),
+            expires_at: expires_at.map(|t| t as u64),
+            last_used_at: last_used_at.map(|t| t as u64),
+            quota
+        }
+    }
+}
+
 pub struct TableInfo {
     pub table_name: String,
     pub token_row_name: String,
     pub manager_state_row_name: String,
 }
 
+// what a backend stores to authenticate a token
+pub struct Credentials {
+    pub state: Option<ManagerState>, // `None` if the stored state isn't a number
+    pub scopes: Option<String>,
+    pub salt: String,
+    pub hash: String,
+    pub old_salt: Option<String>,
+    pub old_hash: Option<String>,
+    pub old_valid_until: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub quota: Option<u32>
+}
+
+// implemented by every storage backend, see `Pool::managers`. backends only store
+// rows, hashing and verifying tokens is done by the provided methods.
+pub trait ManagerStore: Send {
+    fn insert(&mut self, key_id: &str, salt: &str, hash: &str, state: ManagerState,
+        scopes: Option<&[Scope]>, options: &TokenOptions) -> Result<(), types::AnyError>;
+
+    fn credentials(&mut self, key_id: &str) -> Result<Option<Credentials>, types::AnyError>;
+
+    // moves the current hash to the old one, returns whether the key id exists
+    fn replace_hash(&mut self, key_id: &str, salt: &str, hash: &str, old_valid_until: u64)
+        -> Result<bool, types::AnyError>;
+
+    // each returns whether the key id exists
+    fn update_quota(&mut self, key_id: &str, quota: Option<u32>) -> Result<bool, types::AnyError>;
+    fn update_scopes(&mut self, key_id: &str, scopes: &[Scope]) -> Result<bool, types::AnyError>;
+    fn update_state(&mut self, key_id: &str, state: ManagerState) -> Result<bool, types::AnyError>;
+    fn touch(&mut self, key_id: &str) -> Result<(), types::AnyError>;
+
+    fn list(&mut self) -> Result<Vec<ManagerInfo>, types::AnyError>;
+    fn get_info(&mut self, key_id: &str) -> Result<Option<ManagerInfo>, types::AnyError>;
+
+    // returns whether a manager was deleted
+    fn delete(&mut self, key_id: &str) -> Result<bool, types::AnyError>;
+
+    fn add_token(&mut self, token: &str, state: ManagerState, scopes: Option<&[Scope]>, options: &TokenOptions)
+        -> Result<(), types::AnyError>
+    {
+        if token.len() != TOKEN_LENGTH || !ManagerAuth::is_alphanumeric(token) {
+            return Err("Invalid token".into());
+        }
+
+        let salt = random_string(16);
+        let hash = ManagerAuth::hash(&salt, token);
+        self.insert(&ManagerAuth::key_id(token), &salt, &hash, state, scopes, options)
+    }
+
+    // issues a new token with the same key id, the old one keeps working for `grace` seconds
+    fn rotate(&mut self, key_id: &str, grace: u64) -> Result<String, types::AnyError> {
+        let token = format!("{}{}", key_id, random_string(TOKEN_LENGTH - KEY_ID_LENGTH));
+        let salt = random_string(16);
+        // stored as a signed integer, a larger one would wrap and end the grace period at once
+        let until = ManagerAuth::now().saturating_add(grace).min(i64::MAX as u64);
+
+        match self.replace_hash(key_id, &salt, &ManagerAuth::hash(&salt, &token), until)? {
+            false => Err("Unknown key id".into()),
+            true => Ok(token)
+        }
+    }
+
+    fn get_state(&mut self, token: &str)
+        -> ManagerResult<ManagerState>
+    {
+        match self.get_manager(token) {
+            ManagerResult::Ok(manager) => ManagerResult::Ok(manager.state),
+            ManagerResult::Err(why) => ManagerResult::Err(why)
+        }
+    }
+
+    fn get_manager(&mut self, token: &str)
+        -> ManagerResult<Manager>
+    {
+        if token.len() != TOKEN_LENGTH || !ManagerAuth::is_alphanumeric(token) {
+            return ManagerResult::Err(ManagerError::InvalidToken)
+        }
+
+        let key_id = ManagerAuth::key_id(token);
+        let creds = match self.credentials(&key_id) {
+            Ok(Some(creds)) => creds,
+            Ok(None) => return ManagerResult::Err(ManagerError::InvalidToken),
+            Err(_) => return ManagerResult::Err(ManagerError::Storage)
+        };
+
+        let now = ManagerAuth::now() as i64;
+
+        // check the current token, then the one replaced by the last rotation
+        let current = ManagerAuth::verify(&creds.salt, &creds.hash, token);
+        let replaced = !current && match creds {
+            Credentials { old_salt: Some(ref salt), old_hash: Some(ref hash), old_valid_until: Some(until), .. } =>
+                now < until && ManagerAuth::verify(salt, hash, token),
+            _ => false
+        };
+
+        if !current && !replaced {
+            return ManagerResult::Err(ManagerError::InvalidToken);
+        }
+
+        if let Some(expires_at) = creds.expires_at {
+            if expires_at <= now {
+                return ManagerResult::Err(ManagerError::Expired);
+            }
+        }
+
+        let state = match creds.state {
+            Some(state) => state,
+            None => return ManagerResult::Err(ManagerError::InvalidToken)
+        };
+
+        let scopes = match creds.scopes.as_deref().map(Scope::split) {
+            Some(Ok(scopes)) => scopes,
+            Some(Err(_)) => return ManagerResult::Err(ManagerError::UnknownScope),
+            None => Scope::defaults(state)
+        };
+
+        ManagerResult::Ok(Manager { key_id, state, scopes, quota: creds.quota, replaced })
+    }
+}
+
+// the sqlite backend, also home of the token helpers every backend shares
 pub struct ManagerAuth {
     conn: Connection
 }
 
 impl ManagerAuth {
-    pub fn new() -> Result<Self, types::AnyError> {
-        let conn = connect_to_database()?;
+    pub fn new(pool: &SqlitePool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
         Ok(Self { conn })
     }
 
-    pub fn create(conn: Connection) {
-        conn.execute(
+    // replaces every plaintext token with its hash, used by the migrations. fails without
+    // changing anything if a token can't be kept, the rows have to be fixed by hand first.
+    pub(crate) fn hash_plaintext(trs: &Transaction) -> Result<(), types::AnyError> {
+        let has_scopes = trs.prepare("SELECT scopes FROM managers LIMIT 0").is_ok();
+        let query = if has_scopes {
+            "SELECT rowid, token, state, scopes FROM managers"
+        } else {
+            "SELECT rowid, token, state, NULL FROM managers"
+        };
+
+        let mut rows = Vec::new();
+
+        {
+            let mut stmt = trs.prepare(query)?;
+            let iter: _ = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+                let rowid: i64 = row.get(0)?;
+                let token: String = row.get(1)?;
+                let state: i64 = row.get(2)?;
+                let scopes: Option<String> = row.get(3)?;
+                Ok((rowid, token, state, scopes))
+            })?;
+
+            for row in iter {
+                rows.push(row?);
+            }
+        }
+
+        // the key id becomes the primary key, two tokens sharing one would lose one of them
+        let mut problems = Vec::new();
+        let mut key_ids: HashMap<String, i64> = HashMap::new();
+
+        for (rowid, token, _, _) in rows.iter() {
+            if token.len() != TOKEN_LENGTH || !Self::is_alphanumeric(token) {
+                problems.push(format!("row {} doesn't hold a {} character alphanumeric token", rowid, TOKEN_LENGTH));
+                continue;
+            }
+
+            if let Some(other) = key_ids.insert(Self::key_id(token), *rowid) {
+                problems.push(format!("rows {} and {} share the key id {}", other, rowid, Self::key_id(token)));
+            }
+        }
+
+        if problems.len() != 0 {
+            let msg = format!("Can't hash the manager tokens, fix or delete these rows of the managers table first: {}",
+                problems.join("; "));
+            return Err(msg.into());
+        }
+
+        trs.execute("ALTER TABLE managers RENAME TO managers_plaintext", rusqlite::NO_PARAMS)?;
+        trs.execute(
             "
-                CREATE TABLE IF NOT EXISTS managers (
-                    token TEXT PRIMARY KEY,
-                    state INTEGER
+                CREATE TABLE managers (
+                    key_id TEXT PRIMARY KEY,
+                    salt TEXT,
+                    hash TEXT,
+                    state INTEGER,
+                    scopes TEXT,
+                    old_salt TEXT,
+                    old_hash TEXT,
+                    old_valid_until INTEGER
                 )
             ",
             rusqlite::NO_PARAMS
-        ).unwrap();
+        )?;
+
+        for (_, token, state, scopes) in rows {
+            let salt = random_string(16);
+            let query = "INSERT INTO managers (key_id, salt, hash, state, scopes) VALUES (?1, ?2, ?3, ?4, ?5)";
+            trs.execute(query, params![Self::key_id(&token), salt, Self::hash(&salt, &token), state, scopes])?;
+        }
+
+        trs.execute("DROP TABLE managers_plaintext", rusqlite::NO_PARAMS)?;
+        Ok(())
     }
 
-    fn is_alphanumeric(&self, text: &str) -> bool {
+    pub(crate) fn now() -> u64 {
+        let start = SystemTime::now();
+        let dur = start
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards");
+        dur.as_secs()
+    }
+
+    pub(crate) fn is_alphanumeric(text: &str) -> bool {
         for ch in text.as_bytes() {
             if !(*ch as char).is_alphanumeric() {
                 return false;
@@ -75,7 +427,32 @@ impl ManagerAuth {
         true
     }
 
-    fn into_state(&self, value: Value) -> Option<ManagerState> {
+    pub(crate) fn hash(salt: &str, token: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(salt.as_bytes());
+        hasher.update(token.as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    // compares in constant time so the hash can't be guessed byte by byte
+    pub(crate) fn verify(salt: &str, hash: &str, token: &str) -> bool {
+        let computed = Self::hash(salt, token);
+
+        if computed.len() != hash.len() {
+            return false;
+        }
+
+        computed.bytes()
+            .zip(hash.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    // accepts either a whole token or just its key id
+    pub fn key_id(token: &str) -> String {
+        token.chars().take(KEY_ID_LENGTH).collect()
+    }
+
+    fn into_state(value: Value) -> Option<ManagerState> {
         match value {
             Value::Integer(i) => {
                 match i {
@@ -89,7 +466,7 @@ impl ManagerAuth {
         }
     }
 
-    fn into_i16(&self, ms: ManagerState) -> u8 {
+    pub(crate) fn into_i16(ms: ManagerState) -> u8 {
         match ms {
             ManagerState::Disabled => 0,
             ManagerState::Ok => 1,
@@ -98,54 +475,122 @@ impl ManagerAuth {
         }
     }
 
-    pub fn add_token(&self, token: &str, state: ManagerState) -> Result<(), types::AnyError> {
-        // execute query
-        let query = "INSERT INTO managers (token, state) VALUES (?1, ?2)";
-        self.conn.execute(query, params![token, &self.into_i16(state)])?;
-        Ok(())
+    fn query_info<P>(&self, condition: &str, params: P) -> Result<Vec<ManagerInfo>, types::AnyError>
+        where P: IntoIterator, P::Item: rusqlite::ToSql
+    {
+        let query = format!("
+            SELECT key_id, state, scopes, label, owner, created_at, expires_at, last_used_at, quota
+            FROM managers {} ORDER BY created_at ASC
+        ", condition);
+
+        let mut vec = Vec::new();
+        let mut stmt = self.conn.prepare(&query)?;
+        let rows: _ = stmt.query_map(params, |row| {
+            let state: Value = row.get(1)?;
+            let scopes: Option<String> = row.get(2)?;
+            let created_at: Option<i64> = row.get(5)?;
+            let expires_at: Option<i64> = row.get(6)?;
+            let last_used_at: Option<i64> = row.get(7)?;
+            let quota: Option<u32> = row.get(8)?;
+            let times = [created_at, expires_at, last_used_at];
+            Ok((row.get(0)?, state, scopes, row.get(3)?, row.get(4)?, times, quota))
+        })?;
+
+        for row in rows {
+            let (key_id, state, scopes, label, owner, times, quota) = row?;
+            let state = Self::into_state(state).unwrap_or(ManagerState::Unknown);
+            vec.push(ManagerInfo::from_row(key_id, state, scopes, label, owner, times, quota));
+        }
+
+        Ok(vec)
     }
+}
+
+impl ManagerStore for ManagerAuth {
+    fn insert(&mut self, key_id: &str, salt: &str, hash: &str, state: ManagerState,
+        scopes: Option<&[Scope]>, options: &TokenOptions) -> Result<(), types::AnyError>
+    {
+        let query = "
+            INSERT INTO managers (key_id, salt, hash, state, scopes, label, owner, created_at, expires_at, quota)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+        ";
 
-    pub fn update_state(&self, token: &str, state: ManagerState) -> Result<(), types::AnyError> {
-        let query = "UPDATE managers SET state = ?1 WHERE token = ?2";
-        self.conn.execute(query, params![self.into_i16(state), token])?;
+        let scopes = scopes.map(Scope::join);
+        let expires_at = options.expires_at.map(|e| e as i64);
+        self.conn.execute(query, params![key_id, salt, hash, Self::into_i16(state), scopes,
+            options.label, options.owner, Self::now() as i64, expires_at, options.quota])?;
         Ok(())
     }
 
-    pub fn get_state(&self, token: &str)
-        -> ManagerResult<ManagerState>
+    fn credentials(&mut self, key_id: &str) -> Result<Option<Credentials>, types::AnyError> {
+        let query = "
+            SELECT state, scopes, salt, hash, old_salt, old_hash, old_valid_until, expires_at, quota
+            FROM managers WHERE key_id = ?1
+        ";
+
+        let creds = self.conn.query_row(query, params![key_id], |row| {
+            let state: Value = row.get(0)?;
+
+            Ok(Credentials {
+                state: Self::into_state(state),
+                scopes: row.get(1)?,
+                salt: row.get(2)?,
+                hash: row.get(3)?,
+                old_salt: row.get(4)?,
+                old_hash: row.get(5)?,
+                old_valid_until: row.get(6)?,
+                expires_at: row.get(7)?,
+                quota: row.get(8)?
+            })
+        }).optional()?;
+
+        Ok(creds)
+    }
+
+    fn replace_hash(&mut self, key_id: &str, salt: &str, hash: &str, old_valid_until: u64)
+        -> Result<bool, types::AnyError>
     {
-        if token.len() != 32 || !self.is_alphanumeric(token) {
-            return ManagerResult::Err("Invalid token")
-        }
+        let query = "
+            UPDATE managers
+            SET old_salt = salt, old_hash = hash, old_valid_until = ?1, salt = ?2, hash = ?3
+            WHERE key_id = ?4
+        ";
 
-        // prepare query
-        let query = "SELECT * FROM managers WHERE token = ?1";
-        let mut stmt = self.conn.prepare(query).unwrap();
+        Ok(self.conn.execute(query, params![old_valid_until as i64, salt, hash, key_id])? != 0)
+    }
 
-        // execute the query
-        let mut results: Vec<Value> = Vec::new();
-        let iter: _ = stmt.query_map(params![token], |row| row.get(1));
+    fn update_quota(&mut self, key_id: &str, quota: Option<u32>) -> Result<bool, types::AnyError> {
+        let query = "UPDATE managers SET quota = ?1 WHERE key_id = ?2";
+        Ok(self.conn.execute(query, params![quota, key_id])? != 0)
+    }
 
-        // check errors
-        if iter.is_err() {
-            return ManagerResult::Err("Failed to query for token");
-        }
+    fn update_scopes(&mut self, key_id: &str, scopes: &[Scope]) -> Result<bool, types::AnyError> {
+        let query = "UPDATE managers SET scopes = ?1 WHERE key_id = ?2";
+        Ok(self.conn.execute(query, params![Scope::join(scopes), key_id])? != 0)
+    }
 
-        let iter = iter.unwrap();
+    fn update_state(&mut self, key_id: &str, state: ManagerState) -> Result<bool, types::AnyError> {
+        let query = "UPDATE managers SET state = ?1 WHERE key_id = ?2";
+        Ok(self.conn.execute(query, params![Self::into_i16(state), key_id])? != 0)
+    }
 
-        // put rows into vec
-        for row in iter {
-            if let Ok(row) = row {
-                results.push(row);
-            }
-        }
+    fn touch(&mut self, key_id: &str) -> Result<(), types::AnyError> {
+        let query = "UPDATE managers SET last_used_at = ?1 WHERE key_id = ?2";
+        self.conn.execute(query, params![Self::now() as i64, key_id])?;
+        Ok(())
+    }
 
-        if results.len() != 0 {
-            let res = results[0].clone();
-            let state = self.into_state(res).unwrap();
-            return ManagerResult::Ok(state);
-        }
+    fn list(&mut self) -> Result<Vec<ManagerInfo>, types::AnyError> {
+        self.query_info("", rusqlite::NO_PARAMS)
+    }
+
+    fn get_info(&mut self, key_id: &str) -> Result<Option<ManagerInfo>, types::AnyError> {
+        let mut vec = self.query_info("WHERE key_id = ?1", params![key_id])?;
+        Ok(vec.pop())
+    }
 
-        ManagerResult::Err("Invalid token")
+    fn delete(&mut self, key_id: &str) -> Result<bool, types::AnyError> {
+        let query = "DELETE FROM managers WHERE key_id = ?1";
+        Ok(self.conn.execute(query, params![key_id])? != 0)
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,253 @@
+// versioned schema changes, the version of a database is stored in `PRAGMA user_version`.
+// every migration runs in its own transaction together with the version bump.
+//
+// only append to MIGRATIONS, released migrations must never change. databases
+// from before versioning start at 0 with any older schema, which is why the
+// first migrations check what already exists.
+
+// rusqlite
+use rusqlite::{Connection, Transaction};
+
+// crate
+use crate::database::managers::ManagerAuth;
+use crate::helpers::types;
+
+type Up = fn(&Transaction) -> Result<(), types::AnyError>;
+
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    up: Up
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, name: "create tables", up: create_tables },
+    Migration { version: 2, name: "hash manager tokens", up: hash_manager_tokens },
+    Migration { version: 3, name: "manager metadata", up: manager_metadata },
+    Migration { version: 4, name: "integer proxy columns", up: integer_proxy_columns },
+    Migration { version: 5, name: "proxy check schedule", up: proxy_check_schedule },
+    Migration { version: 6, name: "proxy resurrection", up: proxy_resurrection },
+    Migration { version: 7, name: "proxy check history", up: proxy_check_history }
+];
+
+fn has_column(trs: &Transaction, table: &str, column: &str) -> bool {
+    let select = format!("SELECT {} FROM {} LIMIT 0", column, table);
+    trs.prepare(&select).is_ok()
+}
+
+fn create_tables(trs: &Transaction) -> Result<(), types::AnyError> {
+    trs.execute_batch(
+        "
+            CREATE TABLE IF NOT EXISTS proxies (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                schema_ TEXT,
+                address TEXT,
+                port NUMBER,
+                rating REAL,
+                fails NUMBER,
+                blacklisted NUMBER,
+                UNIQUE (address, port)
+            );
+
+            CREATE TABLE IF NOT EXISTS managers (
+                key_id TEXT PRIMARY KEY,
+                salt TEXT,
+                hash TEXT,
+                state INTEGER,
+                scopes TEXT,
+                old_salt TEXT,
+                old_hash TEXT,
+                old_valid_until INTEGER
+            );
+
+            CREATE TABLE IF NOT EXISTS ratelimited (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                website TEXT,
+                address TEXT,
+                port INTEGER,
+                until INTEGER,
+                UNIQUE (website, address, port)
+            );
+
+            CREATE TABLE IF NOT EXISTS proxy_rules (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                website TEXT,
+                kind INTEGER,
+                target TEXT,
+                UNIQUE (website, kind, target)
+            );
+
+            CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                key_id TEXT,
+                timestamp INTEGER,
+                action TEXT,
+                target TEXT,
+                summary TEXT
+            );
+
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'The audit log is append-only');
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'The audit log is append-only');
+            END;
+        "
+    )?;
+
+    Ok(())
+}
+
+// databases created before hashing stored the plaintext token
+fn hash_manager_tokens(trs: &Transaction) -> Result<(), types::AnyError> {
+    if has_column(trs, "managers", "token") {
+        ManagerAuth::hash_plaintext(trs)?;
+    }
+
+    Ok(())
+}
+
+// label, owner, expiry, last use and quota of a token
+fn manager_metadata(trs: &Transaction) -> Result<(), types::AnyError> {
+    let columns = [
+        ("label", "TEXT"), ("owner", "TEXT"), ("created_at", "INTEGER"),
+        ("expires_at", "INTEGER"), ("last_used_at", "INTEGER"), ("quota", "INTEGER")
+    ];
+
+    for (column, kind) in columns.iter() {
+        if !has_column(trs, "managers", column) {
+            let alter = format!("ALTER TABLE managers ADD COLUMN {} {}", column, kind);
+            trs.execute(&alter, rusqlite::NO_PARAMS)?;
+        }
+    }
+
+    Ok(())
+}
+
+// sqlite can't change column types, so the table is rebuilt
+fn integer_proxy_columns(trs: &Transaction) -> Result<(), types::AnyError> {
+    trs.execute_batch(
+        "
+            CREATE TABLE proxies_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                schema_ TEXT,
+                address TEXT,
+                port INTEGER,
+                rating REAL,
+                fails INTEGER,
+                blacklisted INTEGER,
+                UNIQUE (address, port)
+            );
+
+            INSERT INTO proxies_new (id, schema_, address, port, rating, fails, blacklisted)
+                SELECT id, schema_, address, CAST(port AS INTEGER), rating,
+                    CAST(fails AS INTEGER), CAST(blacklisted AS INTEGER)
+                FROM proxies;
+
+            DROP TABLE proxies;
+            ALTER TABLE proxies_new RENAME TO proxies;
+        "
+    )?;
+
+    Ok(())
+}
+
+// when a proxy is checked next, 0 (right away) for existing proxies
+fn proxy_check_schedule(trs: &Transaction) -> Result<(), types::AnyError> {
+    trs.execute_batch(
+        "
+            ALTER TABLE proxies ADD COLUMN next_check_at INTEGER NOT NULL DEFAULT 0;
+            CREATE INDEX proxies_next_check_at ON proxies (blacklisted, next_check_at);
+        "
+    )?;
+
+    Ok(())
+}
+
+// passes in a row of a blacklisted proxy, and when it was blacklisted. proxies
+// that are already blacklisted count from now on.
+fn proxy_resurrection(trs: &Transaction) -> Result<(), types::AnyError> {
+    trs.execute_batch(
+        "
+            ALTER TABLE proxies ADD COLUMN successes INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE proxies ADD COLUMN blacklisted_at INTEGER;
+            UPDATE proxies SET blacklisted_at = CAST(strftime('%s', 'now') AS INTEGER) WHERE blacklisted = 1;
+        "
+    )?;
+
+    Ok(())
+}
+
+// every probe, and hourly and daily rollups of them, see history.rs
+fn proxy_check_history(trs: &Transaction) -> Result<(), types::AnyError> {
+    trs.execute_batch(
+        "
+            CREATE TABLE proxy_checks (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                proxy_id INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL,
+                target TEXT NOT NULL,
+                latency_ms INTEGER,
+                status INTEGER,
+                error TEXT
+            );
+
+            CREATE INDEX proxy_checks_proxy ON proxy_checks (proxy_id, timestamp);
+            CREATE INDEX proxy_checks_timestamp ON proxy_checks (timestamp);
+
+            CREATE TABLE proxy_check_rollups (
+                proxy_id INTEGER NOT NULL,
+                period INTEGER NOT NULL,
+                start INTEGER NOT NULL,
+                probes INTEGER NOT NULL,
+                passes INTEGER NOT NULL,
+                fails INTEGER NOT NULL,
+                target_down INTEGER NOT NULL,
+                latency_sum_ms INTEGER NOT NULL,
+                latency_samples INTEGER NOT NULL,
+                PRIMARY KEY (proxy_id, period, start)
+            );
+
+            CREATE INDEX proxy_check_rollups_start ON proxy_check_rollups (period, start);
+        "
+    )?;
+
+    Ok(())
+}
+
+pub fn latest() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+pub fn version(conn: &Connection) -> Result<u32, types::AnyError> {
+    let version: i64 = conn.query_row("PRAGMA user_version", rusqlite::NO_PARAMS, |row| row.get(0))?;
+    Ok(version as u32)
+}
+
+// applies every pending migration, returns the applied ones
+pub fn run(conn: &mut Connection) -> Result<Vec<&'static Migration>, types::AnyError> {
+    let current = version(conn)?;
+
+    if current > latest() {
+        let msg = format!("The database is at schema version {}, this build only knows up to {}", current, latest());
+        return Err(msg.into());
+    }
+
+    let mut applied = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let trs = conn.transaction()?;
+
+        (migration.up)(&trs)
+            .map_err(|why| format!("Migration {} ({}) failed: {}", migration.version, migration.name, why))?;
+
+        trs.execute_batch(&format!("PRAGMA user_version = {}", migration.version))?;
+        trs.commit()?;
+        applied.push(migration);
+    }
+
+    Ok(applied)
+}
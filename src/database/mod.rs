@@ -1,7 +1,21 @@
-// tables:
-// - proxies [index, schema, proxy address, rating]
-// - managers (auth for managing the proxy) [token: text, state: num /0 = disabled, 1 = ok, 2 = admin/]
+// tables (created and changed by migrations.rs, the schema version is PRAGMA user_version):
+// - proxies [index, schema, proxy address, port, rating, fails, blacklisted, next_check_at, successes, blacklisted_at]
+// - proxy_checks (every probe of the proxy checker) [index, proxy id, timestamp, target, latency_ms, status, error]
+// - proxy_check_rollups (hourly and daily probe counts) [proxy id, period, start, probes, passes, fails, target_down, latency_sum_ms, latency_samples]
+// - managers (auth for managing the proxy) [key_id: text, salt: text, hash: text, state: num /0 = disabled, 1 = ok, 2 = admin/, scopes: text, old_salt, old_hash, old_valid_until, label, owner, created_at, expires_at, last_used_at, quota]
+// - audit_log (append-only record of write operations) [index, key_id, timestamp, action, target, summary]
+// - proxy_rules (permanent per website block/allow lists) [website, kind: num /0 = block, 1 = allow/, target]
 
 pub mod managers;
 pub mod proxies;
-pub mod ratelimited;
\ No newline at end of file
+pub mod ratelimited;
+pub mod history;
+pub mod blocklist;
+pub mod audit;
+pub mod migrations;
+pub mod pool;
+pub mod index;
+pub mod postgres;
+
+#[cfg(test)]
+mod tests;
\ No newline at end of file
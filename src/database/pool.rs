@@ -0,0 +1,152 @@
+// connection pools shared by the server, the background workers and the cli.
+//
+// every table lives in postgres when `database-url` is set and in sqlite otherwise. the
+// sqlite database is still opened and migrated then, but nothing is stored in it.
+//
+// every sqlite connection uses WAL so readers don't block the writer, and waits
+// for locks instead of failing with "database is locked".
+
+// r2d2
+use r2d2_sqlite::SqliteConnectionManager;
+use r2d2_postgres::PostgresConnectionManager;
+
+// postgres
+use postgres::NoTls;
+
+// crate
+use crate::database::audit::{AuditLog, AuditStore};
+use crate::database::blocklist::{Blocklist, BlocklistStore};
+use crate::database::history::{History, HistoryStore};
+use crate::database::managers::{ManagerAuth, ManagerStore};
+use crate::database::proxies::{Proxies, ProxyStore};
+use crate::database::ratelimited::{RateLimited, RateLimitStore};
+use crate::database::{migrations, postgres as pg};
+use crate::helpers::config::General;
+use crate::helpers::types;
+
+// std
+use std::time::Duration;
+
+pub type SqlitePool = r2d2::Pool<SqliteConnectionManager>;
+pub type Connection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+pub type PgPool = r2d2::Pool<PostgresConnectionManager<NoTls>>;
+pub type PgConnection = r2d2::PooledConnection<PostgresConnectionManager<NoTls>>;
+
+// how long a connection waits for another one to release its lock
+const BUSY_TIMEOUT: u64 = 5;
+
+// how long `ping` waits for a connection
+const PING_TIMEOUT: u64 = 2;
+
+#[derive(Clone)]
+pub struct Pool {
+    sqlite: SqlitePool,
+    postgres: Option<PgPool>
+}
+
+impl Pool {
+    pub fn open(general: &General) -> Result<Self, types::AnyError> {
+        let manager = SqliteConnectionManager::file(&general.database_path)
+            .with_init(|conn| {
+                conn.busy_timeout(Duration::from_secs(BUSY_TIMEOUT))?;
+                conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+            });
+
+        let sqlite = r2d2::Pool::builder()
+            .max_size(general.pool_size)
+            .build(manager)?;
+
+        let postgres = match &general.database_url {
+            Some(url) => {
+                let manager = PostgresConnectionManager::new(url.parse()?, NoTls);
+                Some(r2d2::Pool::builder().max_size(general.pool_size).build(manager)?)
+            },
+            None => None
+        };
+
+        Ok(Self { sqlite, postgres })
+    }
+
+    // a connection to the sqlite database
+    pub fn get(&self) -> Result<Connection, types::AnyError> {
+        Ok(self.sqlite.get()?)
+    }
+
+    // runs a trivial query on every backend in use
+    pub fn ping(&self) -> Result<(), types::AnyError> {
+        let timeout = Duration::from_secs(PING_TIMEOUT);
+        self.sqlite.get_timeout(timeout)?.query_row("SELECT 1", rusqlite::NO_PARAMS, |_| Ok(()))?;
+
+        if let Some(postgres) = &self.postgres {
+            postgres.get_timeout(timeout)?.query_one("SELECT 1", &[])?;
+        }
+
+        Ok(())
+    }
+
+    pub fn backend(&self) -> &'static str {
+        if self.postgres.is_some() { "postgres" } else { "sqlite" }
+    }
+
+    // applies the pending migrations of every backend in use, returns what was applied
+    pub fn migrate(&self) -> Result<Vec<String>, types::AnyError> {
+        let mut applied = Vec::new();
+        let mut conn = self.get()?;
+
+        for m in migrations::run(&mut conn)? {
+            applied.push(format!("sqlite migration {}: {}", m.version, m.name));
+        }
+
+        if let Some(postgres) = &self.postgres {
+            let mut conn = postgres.get()?;
+
+            for m in pg::migrate(&mut conn)? {
+                applied.push(format!("postgres migration {}: {}", m.version, m.name));
+            }
+        }
+
+        Ok(applied)
+    }
+
+    pub fn proxies(&self) -> Result<Box<dyn ProxyStore>, types::AnyError> {
+        Ok(match &self.postgres {
+            Some(postgres) => Box::new(pg::proxies::PgProxies::new(postgres)?),
+            None => Box::new(Proxies::new(&self.sqlite)?)
+        })
+    }
+
+    pub fn ratelimited(&self) -> Result<Box<dyn RateLimitStore>, types::AnyError> {
+        Ok(match &self.postgres {
+            Some(postgres) => Box::new(pg::ratelimited::PgRateLimited::new(postgres)?),
+            None => Box::new(RateLimited::new(&self.sqlite)?)
+        })
+    }
+
+    pub fn history(&self) -> Result<Box<dyn HistoryStore>, types::AnyError> {
+        Ok(match &self.postgres {
+            Some(postgres) => Box::new(pg::history::PgHistory::new(postgres)?),
+            None => Box::new(History::new(&self.sqlite)?)
+        })
+    }
+
+    pub fn managers(&self) -> Result<Box<dyn ManagerStore>, types::AnyError> {
+        Ok(match &self.postgres {
+            Some(postgres) => Box::new(pg::managers::PgManagers::new(postgres)?),
+            None => Box::new(ManagerAuth::new(&self.sqlite)?)
+        })
+    }
+    pub fn blocklist(&self) -> Result<Box<dyn BlocklistStore>, types::AnyError> {
+        Ok(match &self.postgres {
+            Some(postgres) => Box::new(pg::blocklist::PgBlocklist::new(postgres)?),
+            None => Box::new(Blocklist::new(&self.sqlite)?)
+        })
+    }
+
+    pub fn audit(&self) -> Result<Box<dyn AuditStore>, types::AnyError> {
+        Ok(match &self.postgres {
+            Some(postgres) => Box::new(pg::audit::PgAuditLog::new(postgres)?),
+            None => Box::new(AuditLog::new(&self.sqlite)?)
+        })
+    }
+}
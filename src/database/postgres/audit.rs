@@ -0,0 +1,65 @@
+// postgres
+use postgres::types::ToSql;
+
+// crate
+use crate::database::audit::{self, AuditStore, AuditEntry, AuditFilter};
+use crate::database::pool::{PgPool, PgConnection};
+use crate::helpers::types;
+
+pub struct PgAuditLog {
+    conn: PgConnection
+}
+
+impl PgAuditLog {
+    pub fn new(pool: &PgPool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+}
+
+impl AuditStore for PgAuditLog {
+    fn record(&mut self, key_id: &str, action: &str, target: &str, summary: &str)
+        -> Result<(), types::AnyError>
+    {
+        let query = "INSERT INTO audit_log (key_id, timestamp, action, target, summary) VALUES ($1, $2, $3, $4, $5)";
+        self.conn.execute(query, &[&key_id, &(audit::now() as i64), &action, &target, &summary])?;
+        Ok(())
+    }
+
+    fn query(&mut self, filter: &AuditFilter, page: u32, per_page: u32)
+        -> Result<Vec<AuditEntry>, types::AnyError>
+    {
+        let query = "
+            SELECT id, key_id, timestamp, action, target, summary FROM audit_log
+            WHERE ($1::TEXT IS NULL OR key_id = $1)
+                AND ($2::TEXT IS NULL OR action = $2)
+                AND ($3::TEXT IS NULL OR target = $3)
+                AND ($4::BIGINT IS NULL OR timestamp >= $4)
+                AND ($5::BIGINT IS NULL OR timestamp <= $5)
+            ORDER BY id DESC LIMIT $6 OFFSET $7
+        ";
+
+        let since = filter.since.map(|t| t as i64);
+        let until = filter.until.map(|t| t as i64);
+        let offset = page as i64 * per_page as i64;
+        let params: [&(dyn ToSql + Sync); 7] = [
+            &filter.key_id, &filter.action, &filter.target, &since, &until, &(per_page as i64), &offset
+        ];
+
+        let rows = self.conn.query(query, &params)?;
+
+        Ok(rows.iter().map(|row| {
+            let id: i32 = row.get(0);
+            let timestamp: i64 = row.get(2);
+
+            AuditEntry {
+                id: id as u32,
+                key_id: row.get(1),
+                timestamp: timestamp as u64,
+                action: row.get(3),
+                target: row.get(4),
+                summary: row.get(5)
+            }
+        }).collect())
+    }
+}
@@ -0,0 +1,59 @@
+// postgres
+use postgres::Row;
+
+// crate
+use crate::database::blocklist::{BlocklistStore, ListEntry, ListKind};
+use crate::database::pool::{PgPool, PgConnection};
+use crate::helpers::types;
+
+pub struct PgBlocklist {
+    conn: PgConnection
+}
+
+impl PgBlocklist {
+    pub fn new(pool: &PgPool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+
+    fn read(row: &Row) -> ListEntry {
+        let kind: i32 = row.get(1);
+        ListEntry { website: row.get(0), kind: ListKind::from_u8(kind as u8), target: row.get(2) }
+    }
+}
+
+impl BlocklistStore for PgBlocklist {
+    fn add(&mut self, entries: Vec<ListEntry>) -> Result<(), types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+
+        for e in entries.iter() {
+            let query = "INSERT INTO proxy_rules (website, kind, target) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING";
+            trs.execute(query, &[&e.website, &(e.kind.into_u8() as i32), &e.target])?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, entries: Vec<ListEntry>) -> Result<(), types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+
+        for e in entries.iter() {
+            let query = "DELETE FROM proxy_rules WHERE website = $1 AND kind = $2 AND target = $3";
+            trs.execute(query, &[&e.website, &(e.kind.into_u8() as i32), &e.target])?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn list(&mut self, website: Option<&str>) -> Result<Vec<ListEntry>, types::AnyError> {
+        let rows = match website {
+            Some(w) => self.conn.query(
+                "SELECT website, kind, target FROM proxy_rules WHERE website = $1 OR website = '*' ORDER BY id ASC", &[&w])?,
+            None => self.conn.query("SELECT website, kind, target FROM proxy_rules ORDER BY id ASC", &[])?
+        };
+
+        Ok(rows.iter().map(Self::read).collect())
+    }
+}
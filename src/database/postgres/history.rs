@@ -0,0 +1,127 @@
+// postgres
+use postgres::types::ToSql;
+
+// crate
+use crate::database::history::{self, HistoryStore, ProbeRecord, Rollup, HOUR, DAY, HOURLY_RETENTION, DAILY_RETENTION};
+use crate::database::pool::{PgPool, PgConnection};
+use crate::helpers::types;
+
+pub struct PgHistory {
+    conn: PgConnection
+}
+
+impl PgHistory {
+    pub fn new(pool: &PgPool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+}
+
+impl HistoryStore for PgHistory {
+    fn record(&mut self, probes: &[ProbeRecord]) -> Result<(), types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+        let insert = "
+            INSERT INTO proxy_checks (proxy_id, timestamp, target, latency_ms, status, error)
+            VALUES ($1, $2, $3, $4, $5, $6)
+        ";
+        let rollup = "
+            INSERT INTO proxy_check_rollups AS r
+            (proxy_id, period, start, probes, passes, fails, target_down, latency_sum_ms, latency_samples)
+            VALUES ($1, $2, $3, 1, $4, $5, $6, $7, $8)
+            ON CONFLICT (proxy_id, period, start) DO UPDATE SET
+                probes = r.probes + 1,
+                passes = r.passes + excluded.passes,
+                fails = r.fails + excluded.fails,
+                target_down = r.target_down + excluded.target_down,
+                latency_sum_ms = r.latency_sum_ms + excluded.latency_sum_ms,
+                latency_samples = r.latency_samples + excluded.latency_samples
+        ";
+
+        for p in probes.iter() {
+            let proxy_id = p.proxy_id as i32;
+            let latency_ms = p.latency_ms.map(|ms| ms as i32);
+            let status = p.status.map(|s| s as i32);
+
+            let params: [&(dyn ToSql + Sync); 6] = [
+                &proxy_id, &(p.timestamp as i64), &p.target, &latency_ms, &status, &p.error
+            ];
+            trs.execute(insert, &params)?;
+
+            let (passes, fails, target_down) = p.counts();
+            let latency_samples = if p.latency_ms.is_some() { 1 } else { 0 };
+
+            for period in [HOUR, DAY].iter() {
+                let params: [&(dyn ToSql + Sync); 8] = [
+                    &proxy_id, &(*period as i64), &(history::bucket(p.timestamp, *period) as i64),
+                    &(passes as i32), &(fails as i32), &(target_down as i32),
+                    &(p.latency_ms.unwrap_or(0) as i64), &(latency_samples as i32)
+                ];
+                trs.execute(rollup, &params)?;
+            }
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn probes(&mut self, proxy_id: u32, since: u64, limit: u32) -> Result<Vec<ProbeRecord>, types::AnyError> {
+        let query = "
+            SELECT timestamp, target, latency_ms, status, error FROM proxy_checks
+            WHERE proxy_id = $1 AND timestamp >= $2
+            ORDER BY timestamp DESC, id DESC LIMIT $3
+        ";
+
+        let rows = self.conn.query(query, &[&(proxy_id as i32), &(since as i64), &(limit as i64)])?;
+        let probes = rows.iter().map(|row| {
+            let timestamp: i64 = row.get(0);
+            let latency_ms: Option<i32> = row.get(2);
+            let status: Option<i32> = row.get(3);
+
+            ProbeRecord {
+                proxy_id,
+                timestamp: timestamp as u64,
+                target: row.get(1),
+                latency_ms: latency_ms.map(|ms| ms as u32),
+                status: status.map(|s| s as u16),
+                error: row.get(4)
+            }
+        });
+
+        Ok(probes.collect())
+    }
+
+    fn rollups(&mut self, proxy_id: u32, period: u64, since: u64, limit: u32) -> Result<Vec<Rollup>, types::AnyError> {
+        let query = "
+            SELECT start, probes, passes, fails, target_down, latency_sum_ms, latency_samples
+            FROM proxy_check_rollups
+            WHERE proxy_id = $1 AND period = $2 AND start >= $3
+            ORDER BY start DESC LIMIT $4
+        ";
+
+        let params: [&(dyn ToSql + Sync); 4] = [
+            &(proxy_id as i32), &(period as i64), &(history::bucket(since, period) as i64), &(limit as i64)
+        ];
+        let rows = self.conn.query(query, &params)?;
+
+        let rollups = rows.iter().map(|row| {
+            let count = |i: usize| row.get::<_, i32>(i) as i64;
+            Rollup::new(row.get(0), [count(1), count(2), count(3), count(4)], row.get(5), count(6))
+        });
+
+        Ok(rollups.collect())
+    }
+
+    fn prune(&mut self, now: u64, probes_before: u64) -> Result<usize, types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+        let mut deleted = trs.execute("DELETE FROM proxy_checks WHERE timestamp < $1", &[&(probes_before as i64)])?;
+
+        for (period, retention) in [(HOUR, HOURLY_RETENTION), (DAY, DAILY_RETENTION)].iter() {
+            let query = "DELETE FROM proxy_check_rollups WHERE period = $1 AND start < $2";
+            let before = now.saturating_sub(*retention) as i64;
+            deleted += trs.execute(query, &[&(*period as i64), &before])?;
+        }
+
+        trs.commit()?;
+        Ok(deleted as usize)
+    }
+}
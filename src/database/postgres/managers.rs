@@ -0,0 +1,141 @@
+// postgres
+use postgres::Row;
+use postgres::types::ToSql;
+
+// crate
+use crate::database::managers::{
+    Credentials, ManagerAuth, ManagerInfo, ManagerState, ManagerStore, Scope, TokenOptions
+};
+use crate::database::pool::{PgPool, PgConnection};
+use crate::helpers::types;
+
+pub struct PgManagers {
+    conn: PgConnection
+}
+
+impl PgManagers {
+    pub fn new(pool: &PgPool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+
+    fn into_state(value: i32) -> ManagerState {
+        match value {
+            0..=2 => (value as u8).into(),
+            _ => ManagerState::Unknown
+        }
+    }
+
+    fn read_info(row: &Row) -> ManagerInfo {
+        let quota: Option<i64> = row.get(8);
+        let times = [row.get(5), row.get(6), row.get(7)];
+
+        ManagerInfo::from_row(row.get(0), Self::into_state(row.get(1)), row.get(2),
+            row.get(3), row.get(4), times, quota.map(|q| q as u32))
+    }
+
+    fn query_info(&mut self, condition: &str, params: &[&(dyn ToSql + Sync)])
+        -> Result<Vec<ManagerInfo>, types::AnyError>
+    {
+        let query = format!("
+            SELECT key_id, state, scopes, label, owner, created_at, expires_at, last_used_at, quota
+            FROM managers {} ORDER BY created_at ASC
+        ", condition);
+
+        let rows = self.conn.query(query.as_str(), params)?;
+        Ok(rows.iter().map(Self::read_info).collect())
+    }
+}
+
+impl ManagerStore for PgManagers {
+    fn insert(&mut self, key_id: &str, salt: &str, hash: &str, state: ManagerState,
+        scopes: Option<&[Scope]>, options: &TokenOptions) -> Result<(), types::AnyError>
+    {
+        let query = "
+            INSERT INTO managers (key_id, salt, hash, state, scopes, label, owner, created_at, expires_at, quota)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ";
+
+        let state = ManagerAuth::into_i16(state) as i32;
+        let scopes = scopes.map(Scope::join);
+        let now = ManagerAuth::now() as i64;
+        let expires_at = options.expires_at.map(|e| e as i64);
+        let quota = options.quota.map(|q| q as i64);
+
+        self.conn.execute(query, &[&key_id, &salt, &hash, &state, &scopes,
+            &options.label, &options.owner, &now, &expires_at, &quota])?;
+        Ok(())
+    }
+
+    fn credentials(&mut self, key_id: &str) -> Result<Option<Credentials>, types::AnyError> {
+        let query = "
+            SELECT state, scopes, salt, hash, old_salt, old_hash, old_valid_until, expires_at, quota
+            FROM managers WHERE key_id = $1
+        ";
+
+        let row = match self.conn.query_opt(query, &[&key_id])? {
+            Some(row) => row,
+            None => return Ok(None)
+        };
+
+        let quota: Option<i64> = row.get(8);
+
+        Ok(Some(Credentials {
+            state: Some(Self::into_state(row.get(0))),
+            scopes: row.get(1),
+            salt: row.get(2),
+            hash: row.get(3),
+            old_salt: row.get(4),
+            old_hash: row.get(5),
+            old_valid_until: row.get(6),
+            expires_at: row.get(7),
+            quota: quota.map(|q| q as u32)
+        }))
+    }
+
+    fn replace_hash(&mut self, key_id: &str, salt: &str, hash: &str, old_valid_until: u64)
+        -> Result<bool, types::AnyError>
+    {
+        let query = "
+            UPDATE managers
+            SET old_salt = salt, old_hash = hash, old_valid_until = $1, salt = $2, hash = $3
+            WHERE key_id = $4
+        ";
+
+        Ok(self.conn.execute(query, &[&(old_valid_until as i64), &salt, &hash, &key_id])? != 0)
+    }
+
+    fn update_quota(&mut self, key_id: &str, quota: Option<u32>) -> Result<bool, types::AnyError> {
+        let quota = quota.map(|q| q as i64);
+        Ok(self.conn.execute("UPDATE managers SET quota = $1 WHERE key_id = $2", &[&quota, &key_id])? != 0)
+    }
+
+    fn update_scopes(&mut self, key_id: &str, scopes: &[Scope]) -> Result<bool, types::AnyError> {
+        let scopes = Scope::join(scopes);
+        Ok(self.conn.execute("UPDATE managers SET scopes = $1 WHERE key_id = $2", &[&scopes, &key_id])? != 0)
+    }
+
+    fn update_state(&mut self, key_id: &str, state: ManagerState) -> Result<bool, types::AnyError> {
+        let state = ManagerAuth::into_i16(state) as i32;
+        Ok(self.conn.execute("UPDATE managers SET state = $1 WHERE key_id = $2", &[&state, &key_id])? != 0)
+    }
+
+    fn touch(&mut self, key_id: &str) -> Result<(), types::AnyError> {
+        let now = ManagerAuth::now() as i64;
+        self.conn.execute("UPDATE managers SET last_used_at = $1 WHERE key_id = $2", &[&now, &key_id])?;
+        Ok(())
+    }
+
+    fn list(&mut self) -> Result<Vec<ManagerInfo>, types::AnyError> {
+        self.query_info("", &[])
+    }
+
+    fn get_info(&mut self, key_id: &str) -> Result<Option<ManagerInfo>, types::AnyError> {
+        let mut vec = self.query_info("WHERE key_id = $1", &[&key_id])?;
+        Ok(vec.pop())
+    }
+
+    fn delete(&mut self, key_id: &str) -> Result<bool, types::AnyError> {
+        Ok(self.conn.execute("DELETE FROM managers WHERE key_id = $1", &[&key_id])? != 0)
+    }
+}
@@ -0,0 +1,183 @@
+// the postgres backend, used when `database-url` is set so several instances can
+// share one database. the schema version is kept in the schema_version table.
+//
+// tables:
+// - proxies [index, schema, proxy address, port, rating, fails, blacklisted, next_check_at, successes, blacklisted_at]
+// - ratelimited [index, website, proxy address, port, until]
+// - proxy_checks [index, proxy id, timestamp, target, latency_ms, status, error]
+// - proxy_check_rollups [proxy id, period, start, probes, passes, fails, target_down, latency_sum_ms, latency_samples]
+// - managers [key_id, salt, hash, state, scopes, old_salt, old_hash, old_valid_until, label, owner, created_at, expires_at, last_used_at, quota]
+// - proxy_rules [index, website, kind, target]
+// - audit_log [index, key id, timestamp, action, target, summary], a trigger aborts every update and delete
+
+pub mod proxies;
+pub mod ratelimited;
+pub mod managers;
+pub mod history;
+pub mod blocklist;
+pub mod audit;
+
+// postgres
+use postgres::Client;
+
+// crate
+use crate::helpers::types;
+
+pub struct PgMigration {
+    pub version: i32,
+    pub name: &'static str,
+    sql: &'static str
+}
+
+// only append, released migrations must never change
+const MIGRATIONS: &[PgMigration] = &[
+    PgMigration {
+        version: 1,
+        name: "create tables",
+        sql: "
+            CREATE TABLE IF NOT EXISTS proxies (
+                id SERIAL PRIMARY KEY,
+                schema_ TEXT NOT NULL,
+                address TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                rating DOUBLE PRECISION NOT NULL,
+                fails INTEGER NOT NULL,
+                blacklisted BOOLEAN NOT NULL,
+                UNIQUE (address, port)
+            );
+
+            CREATE TABLE IF NOT EXISTS ratelimited (
+                id SERIAL PRIMARY KEY,
+                website TEXT NOT NULL,
+                address TEXT NOT NULL,
+                port INTEGER NOT NULL,
+                until BIGINT NOT NULL,
+                UNIQUE (website, address, port)
+            );
+
+            CREATE TABLE IF NOT EXISTS managers (
+                key_id TEXT PRIMARY KEY,
+                salt TEXT NOT NULL,
+                hash TEXT NOT NULL,
+                state INTEGER NOT NULL,
+                scopes TEXT,
+                old_salt TEXT,
+                old_hash TEXT,
+                old_valid_until BIGINT,
+                label TEXT,
+                owner TEXT,
+                created_at BIGINT,
+                expires_at BIGINT,
+                last_used_at BIGINT,
+                quota BIGINT
+            );
+        "
+    },
+    PgMigration {
+        version: 2,
+        name: "proxy check schedule",
+        sql: "
+            ALTER TABLE proxies ADD COLUMN next_check_at BIGINT NOT NULL DEFAULT 0;
+            CREATE INDEX proxies_next_check_at ON proxies (blacklisted, next_check_at);
+        "
+    },
+    PgMigration {
+        version: 3,
+        name: "proxy resurrection",
+        sql: "
+            ALTER TABLE proxies ADD COLUMN successes INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE proxies ADD COLUMN blacklisted_at BIGINT;
+            UPDATE proxies SET blacklisted_at = EXTRACT(EPOCH FROM NOW())::BIGINT WHERE blacklisted;
+        "
+    },
+    PgMigration {
+        version: 4,
+        name: "proxy check history",
+        sql: "
+            CREATE TABLE proxy_checks (
+                id BIGSERIAL PRIMARY KEY,
+                proxy_id INTEGER NOT NULL,
+                timestamp BIGINT NOT NULL,
+                target TEXT NOT NULL,
+                latency_ms INTEGER,
+                status INTEGER,
+                error TEXT
+            );
+
+            CREATE INDEX proxy_checks_proxy ON proxy_checks (proxy_id, timestamp);
+            CREATE INDEX proxy_checks_timestamp ON proxy_checks (timestamp);
+
+            CREATE TABLE proxy_check_rollups (
+                proxy_id INTEGER NOT NULL,
+                period BIGINT NOT NULL,
+                start BIGINT NOT NULL,
+                probes INTEGER NOT NULL,
+                passes INTEGER NOT NULL,
+                fails INTEGER NOT NULL,
+                target_down INTEGER NOT NULL,
+                latency_sum_ms BIGINT NOT NULL,
+                latency_samples INTEGER NOT NULL,
+                PRIMARY KEY (proxy_id, period, start)
+            );
+
+            CREATE INDEX proxy_check_rollups_start ON proxy_check_rollups (period, start);
+        "
+    },
+    PgMigration {
+        version: 5,
+        name: "blocklist and audit log",
+        sql: "
+            CREATE TABLE proxy_rules (
+                id SERIAL PRIMARY KEY,
+                website TEXT NOT NULL,
+                kind INTEGER NOT NULL,
+                target TEXT NOT NULL,
+                UNIQUE (website, kind, target)
+            );
+
+            CREATE TABLE audit_log (
+                id SERIAL PRIMARY KEY,
+                key_id TEXT NOT NULL,
+                timestamp BIGINT NOT NULL,
+                action TEXT NOT NULL,
+                target TEXT NOT NULL,
+                summary TEXT NOT NULL
+            );
+
+            CREATE INDEX audit_log_timestamp ON audit_log (timestamp);
+
+            CREATE FUNCTION audit_log_append_only() RETURNS TRIGGER AS $$
+            BEGIN
+                RAISE EXCEPTION 'The audit log is append-only';
+            END;
+            $$ LANGUAGE plpgsql;
+
+            CREATE TRIGGER audit_log_no_change BEFORE UPDATE OR DELETE ON audit_log
+            FOR EACH ROW EXECUTE PROCEDURE audit_log_append_only();
+        "
+    }
+];
+
+// instances starting at the same time wait on this lock instead of migrating twice
+const MIGRATION_LOCK: i64 = 0x6b696c64696e;
+
+pub fn migrate(conn: &mut Client) -> Result<Vec<&'static PgMigration>, types::AnyError> {
+    let mut trs = conn.transaction()?;
+    trs.execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK])?;
+    trs.batch_execute("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")?;
+
+    let current: Option<i32> = trs.query_one("SELECT MAX(version) FROM schema_version", &[])?.get(0);
+    let current = current.unwrap_or(0);
+    let mut applied = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        trs.batch_execute(migration.sql)
+            .map_err(|why| format!("Migration {} ({}) failed: {}", migration.version, migration.name, why))?;
+
+        trs.execute("INSERT INTO schema_version (version) VALUES ($1)", &[&migration.version])?;
+        applied.push(migration);
+    }
+
+    trs.commit()?;
+    Ok(applied)
+}
@@ -0,0 +1,167 @@
+// postgres
+use postgres::Row;
+use postgres::types::ToSql;
+
+// crate
+use crate::database::proxies::{Proxy, ProxyStore};
+use crate::database::pool::{PgPool, PgConnection};
+use crate::helpers::types;
+
+const COLUMNS: &str = "id, schema_, address, port, rating, fails, blacklisted, next_check_at, successes, blacklisted_at";
+
+pub struct PgProxies {
+    conn: PgConnection
+}
+
+impl PgProxies {
+    pub fn new(pool: &PgPool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+
+    fn read(row: &Row) -> (u32, Proxy) {
+        let key: i32 = row.get(0);
+        let port: i32 = row.get(3);
+        let fails: i32 = row.get(5);
+        let next_check_at: i64 = row.get(7);
+        let successes: i32 = row.get(8);
+        let blacklisted_at: Option<i64> = row.get(9);
+
+        (key as u32, Proxy {
+            schema: row.get(1),
+            address: row.get(2),
+            port: port as u16,
+            rating: row.get(4),
+            fails: fails as u32,
+            blacklisted: row.get(6),
+            next_check_at: next_check_at as u64,
+            successes: successes as u32,
+            blacklisted_at: blacklisted_at.map(|t| t as u64)
+        })
+    }
+
+    // writes the check results of proxies that are (not) blacklisted
+    fn update(&mut self, proxies: Vec<Proxy>, blacklisted: bool) -> Result<(), types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+        let query = "
+            UPDATE proxies SET rating = $1, fails = $2, blacklisted = $3, next_check_at = $4,
+                successes = $5, blacklisted_at = $6
+            WHERE address = $7 AND port = $8 AND blacklisted = $9
+        ";
+
+        for p in proxies.iter() {
+            let params: [&(dyn ToSql + Sync); 9] = [
+                &p.rating, &(p.fails as i32), &p.blacklisted, &(p.next_check_at as i64),
+                &(p.successes as i32), &p.blacklisted_at.map(|t| t as i64),
+                &p.address, &(p.port as i32), &blacklisted
+            ];
+
+            trs.execute(query, &params)?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+}
+
+impl ProxyStore for PgProxies {
+    fn top_rated(&mut self, limit: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError> {
+        let query = format!("SELECT {} FROM proxies WHERE NOT blacklisted ORDER BY rating DESC LIMIT $1", COLUMNS);
+        let rows = self.conn.query(query.as_str(), &[&(limit as i64)])?;
+        Ok(rows.iter().map(Self::read).collect())
+    }
+
+    fn by_id(&mut self, id: u32) -> Result<Option<Proxy>, types::AnyError> {
+        let query = format!("SELECT {} FROM proxies WHERE id = $1", COLUMNS);
+        let row = self.conn.query_opt(query.as_str(), &[&(id as i32)])?;
+        Ok(row.map(|row| Self::read(&row).1))
+    }
+
+    fn census(&mut self) -> Result<Vec<(String, bool, u64)>, types::AnyError> {
+        let query = "SELECT schema_, blacklisted, COUNT(*) FROM proxies GROUP BY schema_, blacklisted";
+        let rows = self.conn.query(query, &[])?;
+
+        Ok(rows.iter().map(|row| {
+            let count: i64 = row.get(2);
+            (row.get(0), row.get(1), count as u64)
+        }).collect())
+    }
+
+    fn after(&mut self, from: u32, pag: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError> {
+        let query = format!("SELECT {} FROM proxies WHERE id > $1 AND NOT blacklisted ORDER BY id ASC LIMIT $2", COLUMNS);
+        let rows = self.conn.query(query.as_str(), &[&(from as i32), &(pag as i64)])?;
+        Ok(rows.iter().map(Self::read).collect())
+    }
+
+    fn claim(&mut self, blacklisted: bool, now: u64, lease_until: u64, limit: u32)
+        -> Result<Vec<(u32, Proxy)>, types::AnyError>
+    {
+        // rows another checker is claiming right now are skipped instead of waited for
+        let columns = COLUMNS.split(", ").map(|c| format!("p.{}", c)).collect::<Vec<_>>().join(", ");
+        let query = format!("
+            WITH due AS (
+                SELECT id, next_check_at FROM proxies
+                WHERE blacklisted = $1 AND next_check_at <= $2
+                ORDER BY next_check_at ASC, id ASC LIMIT $4
+                FOR UPDATE SKIP LOCKED
+            )
+            UPDATE proxies p SET next_check_at = $3 FROM due WHERE p.id = due.id
+            RETURNING {}, due.next_check_at
+        ", columns);
+
+        let params: [&(dyn ToSql + Sync); 4] = [&blacklisted, &(now as i64), &(lease_until as i64), &(limit as i64)];
+        let mut rows = self.conn.query(query.as_str(), &params)?;
+
+        // RETURNING has no order, the longest overdue go first like on sqlite
+        rows.sort_by_key(|row| (row.get::<_, i64>(10), row.get::<_, i32>(0)));
+        Ok(rows.iter().map(Self::read).collect())
+    }
+
+    fn insert_proxies(&mut self, proxies: Vec<Proxy>) -> Result<Vec<Proxy>, types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+        let query = "
+            INSERT INTO proxies (schema_, address, port, rating, fails, blacklisted, next_check_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT DO NOTHING
+        ";
+
+        let mut inserted = Vec::new();
+
+        for p in proxies.into_iter() {
+            let params: [&(dyn ToSql + Sync); 7] = [
+                &p.schema, &p.address, &(p.port as i32), &p.rating, &(p.fails as i32), &p.blacklisted, &(p.next_check_at as i64)
+            ];
+
+            if trs.execute(query, &params)? == 1 {
+                inserted.push(p);
+            }
+        }
+
+        trs.commit()?;
+        Ok(inserted)
+    }
+
+    fn update_proxies(&mut self, proxies: Vec<Proxy>) -> Result<(), types::AnyError> {
+        self.update(proxies, false)
+    }
+
+    fn update_blacklisted(&mut self, proxies: Vec<Proxy>) -> Result<(), types::AnyError> {
+        self.update(proxies, true)
+    }
+
+    fn delete_proxies(&mut self, proxies: Vec<Proxy>) -> Result<(), types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+        let query = "DELETE FROM proxies WHERE address = $1 AND port = $2";
+
+        for p in proxies.iter() {
+            trs.execute(query, &[&p.address, &(p.port as i32)])?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn purge(&mut self, before: u64) -> Result<usize, types::AnyError> {
+        let query = "DELETE FROM proxies WHERE blacklisted AND blacklisted_at <= $1";
+        Ok(self.conn.execute(query, &[&(before as i64)])? as usize)
+    }
+}
@@ -0,0 +1,86 @@
+// crate
+use crate::database::ratelimited::{Entries, RateLimitEntry, RateLimitStore};
+use crate::database::pool::{PgPool, PgConnection};
+use crate::helpers::types;
+
+pub struct PgRateLimited {
+    conn: PgConnection
+}
+
+impl PgRateLimited {
+    pub fn new(pool: &PgPool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
+    }
+}
+
+impl RateLimitStore for PgRateLimited {
+    fn add(&mut self, entries: Entries) -> Result<(), types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+        let query = "
+            INSERT INTO ratelimited (website, address, port, until)
+            VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING
+        ";
+
+        for e in entries.iter() {
+            trs.execute(query, &[&e.website, &e.address, &(e.port as i32), &(e.until as i64)])?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, entries: Entries) -> Result<(), types::AnyError> {
+        let mut trs = self.conn.transaction()?;
+        let query = "DELETE FROM ratelimited WHERE website = $1 AND address = $2 AND port = $3";
+
+        for e in entries.iter() {
+            trs.execute(query, &[&e.website, &e.address, &(e.port as i32)])?;
+        }
+
+        trs.commit()?;
+        Ok(())
+    }
+
+    fn active(&mut self, now: u64) -> Result<Vec<(String, u64)>, types::AnyError> {
+        let query = "SELECT website, COUNT(*) FROM ratelimited WHERE until > $1 GROUP BY website";
+        let rows = self.conn.query(query, &[&(now as i64)])?;
+
+        Ok(rows.iter().map(|row| {
+            let count: i64 = row.get(1);
+            (row.get(0), count as u64)
+        }).collect())
+    }
+
+    fn limited_proxies(&mut self, now: u64) -> Result<u64, types::AnyError> {
+        let query = "
+            SELECT COUNT(*) FROM proxies p
+            WHERE NOT p.blacklisted AND EXISTS (
+                SELECT 1 FROM ratelimited r WHERE r.address = p.address AND r.port = p.port AND r.until > $1
+            )
+        ";
+
+        let count: i64 = self.conn.query_one(query, &[&(now as i64)])?.get(0);
+        Ok(count as u64)
+    }
+
+    fn after(&mut self, from: u32, pag: u32) -> Result<Vec<(u32, RateLimitEntry)>, types::AnyError> {
+        let query = "SELECT id, website, address, port, until FROM ratelimited WHERE id > $1 ORDER BY id ASC LIMIT $2";
+        let rows = self.conn.query(query, &[&(from as i32), &(pag as i64)])?;
+
+        let entries = rows.iter().map(|row| {
+            let key: i32 = row.get(0);
+            let port: i32 = row.get(3);
+            let until: i64 = row.get(4);
+
+            (key as u32, RateLimitEntry {
+                website: row.get(1),
+                address: row.get(2),
+                port: port as u16,
+                until: until as u64
+            })
+        });
+
+        Ok(entries.collect())
+    }
+}
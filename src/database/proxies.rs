@@ -1,16 +1,19 @@
-// - proxies [index, schema, proxy address, rating]
+// - proxies [index, schema, proxy address, port, rating, fails, blacklisted, next_check_at, successes, blacklisted_at]
 
 // rusqlite
-use rusqlite::Connection;
 use rusqlite::params;
+use rusqlite::TransactionBehavior;
 
 // crate
 use crate::helpers::types;
-use crate::connect_to_database;
+use crate::database::pool::{SqlitePool, Connection};
 
 // serde
 use serde_derive::Serialize;
 
+// std
+use std::time::{SystemTime, UNIX_EPOCH};
+
 macro_rules! query_proxies {
     ($stmt:expr, $params:expr) => {
         $stmt.query_map($params, |row| {
@@ -22,16 +25,20 @@ macro_rules! query_proxies {
             let rating: f64 = row.get(4)?;
             let fails: u32 = row.get(5)?;
             let blacklisted: bool = row.get(6)?;
+            let next_check_at: i64 = row.get(7)?;
+            let successes: u32 = row.get(8)?;
+            let blacklisted_at: Option<i64> = row.get(9)?;
 
             Ok((key, Proxy { schema, address, port,
-                rating, fails, blacklisted }))
+                rating, fails, blacklisted, next_check_at: next_check_at as u64,
+                successes, blacklisted_at: blacklisted_at.map(|t| t as u64) }))
         })
     };
 }
 
 macro_rules! order_proxies {
     ($name:ident, $param:ident, $param_type:ident, $column:expr) => {
-        pub fn $name(&self, $param: $param_type, pag: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError> {
+        fn $name(&mut self, $param: $param_type, pag: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError> {
             // set up query
             let query = format!("SELECT * FROM proxies WHERE {} > ?1 AND blacklisted = 0 ORDER BY {} ASC LIMIT ?2", stringify!($column), stringify!($column));
             let params = params![$param, pag];
@@ -67,7 +74,7 @@ macro_rules! bulk_sql {
 
 macro_rules! bulk_sql_function {
     ($f:ident, $query:expr, $row_name:ident, $params:expr) => {
-        pub fn $f(&mut self, proxies: Vec<Proxy>)
+        fn $f(&mut self, proxies: Vec<Proxy>)
             -> Result<(), types::AnyError>
         {
             bulk_sql!(self.conn, proxies, $query, $row_name, $params);
@@ -84,38 +91,108 @@ pub struct Proxy {
     pub rating: f64,
     pub fails: u32,
     pub blacklisted: bool,
+
+    #[serde(skip_serializing)]
+    pub next_check_at: u64, // secs, set by the proxy checker
+
+    #[serde(skip_serializing)]
+    pub successes: u32, // passed checks in a row while blacklisted
+
+    #[serde(skip_serializing)]
+    pub blacklisted_at: Option<u64>,
+}
+
+impl Proxy {
+    // parses "schema://address:port"
+    pub fn parse(url: &str) -> Option<Self> {
+        let parts = url.trim().split("://").collect::<Vec<&str>>();
+
+        if parts.len() != 2 {
+            return None;
+        }
+
+        let sub_parts = parts[1].split(":").collect::<Vec<&str>>();
+
+        if sub_parts.len() != 2 {
+            return None;
+        }
+
+        Some(Proxy {
+            schema: parts[0].into(),
+            address: sub_parts[0].into(),
+            port: sub_parts[1].replace("/", "").parse::<u16>().ok()?,
+            rating: 0.0f64, fails: 0,
+            blacklisted: false,
+            next_check_at: 0,
+            successes: 0,
+            blacklisted_at: None
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("{}://{}:{}", self.schema, self.address, self.port)
+    }
 }
 
+// implemented by every storage backend, see `Pool::proxies`
+pub trait ProxyStore: Send {
+    // the best live proxies first
+    fn top_rated(&mut self, limit: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError>;
+
+    // a live or blacklisted proxy
+    fn by_id(&mut self, id: u32) -> Result<Option<Proxy>, types::AnyError>;
+
+    // live proxies with an id above `from`, in id order
+    fn after(&mut self, from: u32, pag: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError>;
+
+    // up to `limit` live or blacklisted proxies that are due for a check at `now`, the longest
+    // overdue first. they're leased by moving their next check to `lease_until`, so another
+    // checker sharing the database skips them until the lease runs out.
+    fn claim(&mut self, blacklisted: bool, now: u64, lease_until: u64, limit: u32)
+        -> Result<Vec<(u32, Proxy)>, types::AnyError>;
+
+    // proxies that already exist are skipped, returns the ones that were inserted
+    fn insert_proxies(&mut self, proxies: Vec<Proxy>) -> Result<Vec<Proxy>, types::AnyError>;
+
+    // updates the check results of live proxies
+    fn update_proxies(&mut self, proxies: Vec<Proxy>) -> Result<(), types::AnyError>;
+
+    // same for blacklisted proxies, which can come back to life this way
+    fn update_blacklisted(&mut self, proxies: Vec<Proxy>) -> Result<(), types::AnyError>;
+
+    // live and blacklisted proxies
+    fn delete_proxies(&mut self, proxies: Vec<Proxy>) -> Result<(), types::AnyError>;
+
+    // deletes the proxies that were blacklisted at or before `before`, returns how many
+    fn purge(&mut self, before: u64) -> Result<usize, types::AnyError>;
+
+    // how many proxies there are per (schema, blacklisted)
+    fn census(&mut self) -> Result<Vec<(String, bool, u64)>, types::AnyError>;
+}
+
+pub(crate) fn now() -> u64 {
+    let start = SystemTime::now();
+    let dur = start
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+    dur.as_secs()
+}
+
+// the sqlite backend
+// TODO: Store IP address as integer instead of text
 pub struct Proxies {
     conn: Connection,
 }
 
 impl Proxies {
-    pub fn new() -> Result<Self, types::AnyError> {
-        let conn = connect_to_database()?;
+    pub fn new(pool: &SqlitePool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
         Ok(Self { conn })
     }
+}
 
-    // TODO: Store IP address as integer instead of text
-    pub fn create(conn: Connection) {
-        conn.execute(
-            "
-                CREATE TABLE IF NOT EXISTS proxies (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    schema_ TEXT,
-                    address TEXT,
-                    port NUMBER,
-                    rating REAL,
-                    fails NUMBER,
-                    blacklisted NUMBER,
-                    UNIQUE (address, port)
-                )
-            ",
-            rusqlite::NO_PARAMS
-        ).unwrap();
-    }
-
-    pub fn top_rated(&self, limit: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError> {
+impl ProxyStore for Proxies {
+    fn top_rated(&mut self, limit: u32) -> Result<Vec<(u32, Proxy)>, types::AnyError> {
         let mut proxies = Vec::new();
 
         // query proxies
@@ -131,20 +208,100 @@ impl Proxies {
         Ok(proxies)
     }
 
+    fn by_id(&mut self, id: u32) -> Result<Option<Proxy>, types::AnyError> {
+        let mut stmt = self.conn.prepare("SELECT * FROM proxies WHERE id = ?1")?;
+        let mut rows = query_proxies!(stmt, params![id])?;
+
+        match rows.next() {
+            Some(row) => Ok(Some(row?.1)),
+            None => Ok(None)
+        }
+    }
+
+    fn claim(&mut self, blacklisted: bool, now: u64, lease_until: u64, limit: u32)
+        -> Result<Vec<(u32, Proxy)>, types::AnyError>
+    {
+        let query = "
+            SELECT * FROM proxies
+            WHERE blacklisted = ?1 AND next_check_at <= ?2
+            ORDER BY next_check_at ASC, id ASC LIMIT ?3
+        ";
+
+        // takes the write lock up front, so two processes can't claim the same proxies
+        let trs = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut vec = Vec::new();
+
+        {
+            let mut stmt = trs.prepare(query)?;
+            let rows = query_proxies!(stmt, params![blacklisted, now as i64, limit])?;
+
+            for row in rows {
+                vec.push(row?);
+            }
+        }
+
+        for (id, proxy) in vec.iter_mut() {
+            trs.execute("UPDATE proxies SET next_check_at = ?1 WHERE id = ?2", params![lease_until as i64, *id])?;
+            proxy.next_check_at = lease_until;
+        }
+
+        trs.commit()?;
+        Ok(vec)
+    }
+
+    fn insert_proxies(&mut self, proxies: Vec<Proxy>) -> Result<Vec<Proxy>, types::AnyError> {
+        let trs = self.conn.transaction()?;
+        let query = "INSERT OR IGNORE INTO proxies (schema_, address, port, rating, fails, blacklisted, next_check_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+        let mut inserted = Vec::new();
+
+        for proxy in proxies.into_iter() {
+            let params = params![proxy.schema, proxy.address, proxy.port, proxy.rating, proxy.fails, proxy.blacklisted, proxy.next_check_at as i64];
+
+            if trs.execute(query, params)? == 1 {
+                inserted.push(proxy);
+            }
+        }
+
+        trs.commit()?;
+        Ok(inserted)
+    }
+
     // bulk sql functions
-    bulk_sql_function!(insert_proxies,
-        "INSERT OR IGNORE INTO proxies (schema_, address, port, rating, fails, blacklisted) VALUES (?1, ?2, ?3, ?4, ?5, ?6) WHERE blacklisted = 0",
-        proxy, params![proxy.schema, proxy.address, proxy.port, proxy.rating, proxy.fails, proxy.blacklisted]);
 
     bulk_sql_function!(update_proxies,
-        "UPDATE proxies SET rating = ?1, fails = ?2, blacklisted = ?3 WHERE (address = ?4 AND port = ?5) AND blacklisted = 0",
-        proxy, params![proxy.rating, proxy.fails, proxy.blacklisted, proxy.address, proxy.port]);
+        "UPDATE proxies SET rating = ?1, fails = ?2, blacklisted = ?3, next_check_at = ?4, successes = ?5, blacklisted_at = ?6 WHERE (address = ?7 AND port = ?8) AND blacklisted = 0",
+        proxy, params![proxy.rating, proxy.fails, proxy.blacklisted, proxy.next_check_at as i64, proxy.successes, proxy.blacklisted_at.map(|t| t as i64), proxy.address, proxy.port]);
+
+    bulk_sql_function!(update_blacklisted,
+        "UPDATE proxies SET rating = ?1, fails = ?2, blacklisted = ?3, next_check_at = ?4, successes = ?5, blacklisted_at = ?6 WHERE (address = ?7 AND port = ?8) AND blacklisted = 1",
+        proxy, params![proxy.rating, proxy.fails, proxy.blacklisted, proxy.next_check_at as i64, proxy.successes, proxy.blacklisted_at.map(|t| t as i64), proxy.address, proxy.port]);
 
     bulk_sql_function!(delete_proxies,
-        "DELETE FROM proxies WHERE address = ?1 AND port = ?2 AND blacklisted = 0",
+        "DELETE FROM proxies WHERE address = ?1 AND port = ?2",
         proxy, params![proxy.address, proxy.port]);
 
+    fn purge(&mut self, before: u64) -> Result<usize, types::AnyError> {
+        let query = "DELETE FROM proxies WHERE blacklisted = 1 AND blacklisted_at <= ?1";
+        Ok(self.conn.execute(query, params![before as i64])?)
+    }
+
+    fn census(&mut self) -> Result<Vec<(String, bool, u64)>, types::AnyError> {
+        let query = "SELECT schema_, blacklisted, COUNT(*) FROM proxies GROUP BY schema_, blacklisted";
+        let mut stmt = self.conn.prepare(query)?;
+        let rows = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+            let count: i64 = row.get(2)?;
+            Ok((row.get(0)?, row.get(1)?, count as u64))
+        })?;
+
+        let mut vec = Vec::new();
+
+        for row in rows {
+            vec.push(row?);
+        }
+
+        Ok(vec)
+    }
+
     // order functions
     order_proxies!(after, from, u32, "id");
-    order_proxies!(over, min_rating, f64, "rating");
 }
\ No newline at end of file
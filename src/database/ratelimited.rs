@@ -1,14 +1,13 @@
 // rusqlite
-use rusqlite::Connection;
 use rusqlite::params;
 
 // crate
 use crate::helpers::types;
-use crate::database::proxies::Proxy;
-use crate::connect_to_database;
+use crate::database::pool::{SqlitePool, Connection};
 
-type Entries = Vec<RateLimitEntry>;
+pub type Entries = Vec<RateLimitEntry>;
 
+// the sqlite backend
 pub struct RateLimited {
     conn: Connection
 }
@@ -21,29 +20,53 @@ pub struct RateLimitEntry {
     pub until: u64
 }
 
-impl RateLimited {
-    pub fn new() -> Result<Self, types::AnyError> {
-        let conn = connect_to_database()?;
-        Ok(Self { conn })
+// implemented by every storage backend, see `Pool::ratelimited`
+pub trait RateLimitStore: Send {
+    // entries that already exist are skipped
+    fn add(&mut self, entries: Entries) -> Result<(), types::AnyError>;
+    fn remove(&mut self, entries: Entries) -> Result<(), types::AnyError>;
+
+    // entries with an id above `from`, in id order
+    fn after(&mut self, from: u32, pag: u32) -> Result<Vec<(u32, RateLimitEntry)>, types::AnyError>;
+
+    // entries that are active at `now`, per website
+    fn active(&mut self, now: u64) -> Result<Vec<(String, u64)>, types::AnyError>;
+
+    // live proxies with an entry that is active at `now`
+    fn limited_proxies(&mut self, now: u64) -> Result<u64, types::AnyError>;
+
+    // pages through every entry, collecting what `f` returns for each page
+    fn read_ratelimited(&mut self, pag: u32, f: &dyn Fn(Vec<(u32, RateLimitEntry)>) -> Entries)
+        -> Result<Entries, types::AnyError>
+    {
+        let mut idx = 0;
+        let mut proxies = Vec::new();
+
+        loop {
+            // get vec and set idx
+            let vec = self.after(idx, pag)?;
+
+            if vec.len() == 0 {
+                break;
+            }
+
+            idx = vec[vec.len() - 1].0;
+            proxies.append(&mut f(vec));
+        }
+
+        Ok(proxies)
     }
+}
 
-    pub fn create(conn: Connection) {
-        conn.execute(
-            "
-                CREATE TABLE IF NOT EXISTS ratelimited (
-                    id INTEGER PRIMARY KEY AUTOINCREMENT,
-                    website TEXT,
-                    address TEXT,
-                    port INTEGER,
-                    until INTEGER,
-                    UNIQUE (website, address, port)
-                )
-            ",
-            rusqlite::NO_PARAMS
-        ).unwrap();
+impl RateLimited {
+    pub fn new(pool: &SqlitePool) -> Result<Self, types::AnyError> {
+        let conn = pool.get()?;
+        Ok(Self { conn })
     }
+}
 
-    pub fn add(&mut self, entries: Entries) -> Result<(), types::AnyError> {
+impl RateLimitStore for RateLimited {
+    fn add(&mut self, entries: Entries) -> Result<(), types::AnyError> {
         let trs = self.conn.transaction()?;
 
         for e in entries.iter() {
@@ -55,7 +78,7 @@ impl RateLimited {
         Ok(())
     }
 
-    pub fn remove(&mut self, entries: Entries) -> Result<(), types::AnyError> {
+    fn remove(&mut self, entries: Entries) -> Result<(), types::AnyError> {
         let trs = self.conn.transaction()?;
 
         for e in entries.iter() {
@@ -67,33 +90,36 @@ impl RateLimited {
         Ok(())
     }
 
-    pub fn get_ratelimited(&mut self, website: &str, proxies: Vec<Proxy>)
-        -> Result<Vec<Proxy>, types::AnyError>
-    {
-        let trs = self.conn.transaction()?;
-        let mut ratelimited = Vec::new();
+    fn active(&mut self, now: u64) -> Result<Vec<(String, u64)>, types::AnyError> {
+        let query = "SELECT website, COUNT(*) FROM ratelimited WHERE until > ?1 GROUP BY website";
+        let mut stmt = self.conn.prepare(query)?;
+        let rows = stmt.query_map(params![now as i64], |row| {
+            let count: i64 = row.get(1)?;
+            Ok((row.get(0)?, count as u64))
+        })?;
 
-        for p in proxies {
-            let query = "SELECT * FROM ratelimited WHERE (website = ?1 OR website = '*') AND address = ?2 AND port = ?3";
+        let mut vec = Vec::new();
 
-            // query
-            let params = params![website, p.address, p.port];
-            let mut stmt = trs.prepare(query)?;
-            let rows: _ = stmt.query_map(params, |_| Ok(1));
+        for row in rows {
+            vec.push(row?);
+        }
 
-            if rows.is_err() {
-                continue;
-            }
+        Ok(vec)
+    }
 
-            if rows.unwrap().size_hint().0 != 0 {
-                ratelimited.push(p);
-            }
-        }
+    fn limited_proxies(&mut self, now: u64) -> Result<u64, types::AnyError> {
+        let query = "
+            SELECT COUNT(*) FROM proxies p
+            WHERE p.blacklisted = 0 AND EXISTS (
+                SELECT 1 FROM ratelimited r WHERE r.address = p.address AND r.port = p.port AND r.until > ?1
+            )
+        ";
 
-        Ok(ratelimited)
+        let count: i64 = self.conn.query_row(query, params![now as i64], |row| row.get(0))?;
+        Ok(count as u64)
     }
 
-    fn after(&self, from: u32, pag: u32) -> Result<Vec<(u32, RateLimitEntry)>, types::AnyError> {
+    fn after(&mut self, from: u32, pag: u32) -> Result<Vec<(u32, RateLimitEntry)>, types::AnyError> {
         // set up query
         let query = "SELECT * FROM ratelimited WHERE id > ?1 ORDER BY id ASC LIMIT ?2";
         let params = params![from, pag];
@@ -119,26 +145,4 @@ impl RateLimited {
 
         Ok(vec)
     }
-
-    pub fn read_ratelimited<F, W>(&self, pag: u32, f: F)
-        -> Result<Vec<W>, types::AnyError>
-        where F: Fn(Vec<(u32, RateLimitEntry)>) -> Vec<W>
-    {
-        let mut idx = 0;
-        let mut proxies = Vec::new();
-
-        loop {
-            // get vec and set idx
-            let vec = self.after(idx, pag)?;
-
-            if vec.len() == 0 {
-                break;
-            }
-
-            idx = vec[vec.len() - 1].0;
-            proxies.append(&mut f(vec));
-        }
-
-        Ok(proxies)
-    }
-}
\ No newline at end of file
+}
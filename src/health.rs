@@ -0,0 +1,51 @@
+// crate
+use crate::database::proxies::now;
+use crate::helpers::config::HttpServer;
+
+// std
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// When the background threads last finished a pass, read by `GET /ready`. A pass of the
+// proxy checker counts once `update` returns, whether or not any proxy was due.
+pub struct Health {
+    pub min_proxies: usize,
+    pub max_staleness: u64, // secs
+
+    started: u64,
+    checked: AtomicU64, // unix secs, 0 before the first pass
+    ratelimits_updated: AtomicU64,
+}
+
+impl Health {
+    pub fn new(server: &HttpServer) -> Self {
+        Self {
+            min_proxies: server.ready_min_proxies as usize,
+            max_staleness: server.ready_max_staleness,
+            started: now(),
+            checked: AtomicU64::new(0),
+            ratelimits_updated: AtomicU64::new(0)
+        }
+    }
+
+    pub fn checker_passed(&self) {
+        self.checked.store(now(), Ordering::Relaxed);
+    }
+
+    pub fn ratelimits_passed(&self) {
+        self.ratelimits_updated.store(now(), Ordering::Relaxed);
+    }
+
+    pub fn last_check(&self) -> Option<u64> {
+        Some(self.checked.load(Ordering::Relaxed)).filter(|at| *at != 0)
+    }
+
+    pub fn last_ratelimit_update(&self) -> Option<u64> {
+        Some(self.ratelimits_updated.load(Ordering::Relaxed)).filter(|at| *at != 0)
+    }
+
+    // the first pass gets the staleness window from the start
+    pub fn checker_stale(&self) -> bool {
+        let since = self.last_check().unwrap_or(self.started);
+        now().saturating_sub(since) > self.max_staleness
+    }
+}
@@ -1,6 +1,30 @@
+// leveled logging to stdout or a file, as text or one json object per line.
+// a line carries a timestamp, the level, the message and key-value fields, e.g.
+//
+// 2026-01-01T12:00:00.000Z [Info] ProxyManager: Successfully updated 3 proxies. action=update amount=3
+// {"timestamp":"2026-01-01T12:00:00.000Z","level":"info","message":"...","action":"update","amount":3}
+//
+// a log file is rotated once it grows past `max-size`: file -> file.1 -> ... -> file.<keep>
+
+// serde
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+// std
 use std::fmt::{Formatter, Display as FmtDisplay, Result as FmtResult};
-#[derive(Debug, Clone)]
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// super
+use super::config::LoggingSettings;
+use super::types;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Level {
+    Debug,
     Info,
     Warn,
     Error,
@@ -13,13 +37,244 @@ impl FmtDisplay for Level {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// a key-value pair of a line, e.g. ("proxy_id", &42)
+pub type Field<'a> = (&'a str, &'a dyn FieldValue);
+
+// numbers and booleans stay numbers and booleans in json, anything else is a string
+pub trait FieldValue {
+    fn value(&self) -> Value;
+}
+
+macro_rules! field_values {
+    ($($t:ty),*) => {
+        $(
+            impl FieldValue for $t {
+                fn value(&self) -> Value {
+                    Value::from(*self)
+                }
+            }
+        )*
+    };
+}
+
+field_values!(bool, u16, u32, u64, usize, i32, i64, f64);
+
+impl FieldValue for str {
+    fn value(&self) -> Value {
+        Value::String(self.into())
+    }
+}
+
+impl FieldValue for String {
+    fn value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl FieldValue for types::AnyError {
+    fn value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl<T: FieldValue + ?Sized> FieldValue for &T {
+    fn value(&self) -> Value {
+        (**self).value()
+    }
+}
+
 #[derive(Clone)]
-pub struct Logger {}
+pub struct Logger {
+    inner: Arc<Inner>
+}
+
+struct Inner {
+    level: Level,
+    format: LogFormat,
+    output: Mutex<Output>
+}
+
+enum Output {
+    Stdout,
+    File(LogFile)
+}
+
+struct LogFile {
+    path: String,
+    file: File,
+    size: u64,
+    max_size: u64, // bytes, 0 never rotates
+    keep: u32
+}
 
 impl Logger {
-    pub fn new() -> Self { Self {} }
+    // info and above as text on stdout, used until the config is loaded
+    pub fn new() -> Self {
+        Self::with(Level::Info, LogFormat::Text, Output::Stdout)
+    }
+
+    pub fn open(settings: &LoggingSettings) -> Result<Self, types::AnyError> {
+        let output = match &settings.file {
+            Some(path) => Output::File(LogFile::open(path, settings.max_size, settings.keep)?),
+            None => Output::Stdout
+        };
+
+        Ok(Self::with(settings.level, settings.format, output))
+    }
+
+    fn with(level: Level, format: LogFormat, output: Output) -> Self {
+        let inner = Inner { level, format, output: Mutex::new(output) };
+        Self { inner: Arc::new(inner) }
+    }
+
+    pub fn enabled(&self, level: Level) -> bool {
+        level >= self.inner.level
+    }
 
     pub fn log(&self, level: Level, msg: &str) {
-        println!("[{:?}] {}", level, msg);
+        self.log_with(level, msg, &[]);
     }
-}
\ No newline at end of file
+
+    pub fn log_with(&self, level: Level, msg: &str, fields: &[Field]) {
+        if !self.enabled(level) {
+            return;
+        }
+
+        let line = match self.inner.format {
+            LogFormat::Text => Self::text(level, msg, fields),
+            LogFormat::Json => Self::json(level, msg, fields)
+        };
+
+        // a poisoned lock only means another thread panicked mid-write
+        let mut output = match self.inner.output.lock() {
+            Ok(output) => output,
+            Err(poisoned) => poisoned.into_inner()
+        };
+
+        output.write(&line);
+    }
+
+    fn text(level: Level, msg: &str, fields: &[Field]) -> String {
+        let mut line = format!("{} [{}] {}", timestamp(), level, msg);
+
+        for (key, value) in fields.iter() {
+            let value = match value.value() {
+                Value::String(s) => s,
+                value => value.to_string()
+            };
+
+            if value.len() == 0 || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+                line.push_str(&format!(" {}={:?}", key, value));
+            } else {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+        }
+
+        line
+    }
+
+    // written by hand to keep the keys in order
+    fn json(level: Level, msg: &str, fields: &[Field]) -> String {
+        let quote = |s: &str| Value::String(s.into()).to_string();
+        let level = level.to_string().to_lowercase();
+
+        let mut line = format!("{{\"timestamp\":\"{}\",\"level\":\"{}\",\"message\":{}", timestamp(), level, quote(msg));
+
+        for (key, value) in fields.iter() {
+            line.push_str(&format!(",{}:{}", quote(key), value.value()));
+        }
+
+        line.push('}');
+        line
+    }
+}
+
+impl Output {
+    fn write(&mut self, line: &str) {
+        match self {
+            Self::Stdout => println!("{}", line),
+            Self::File(file) => {
+                if let Err(why) = file.write(line) {
+                    eprintln!("Couldn't write to the log file: {}", why);
+                    eprintln!("{}", line);
+                }
+            }
+        }
+    }
+}
+
+impl LogFile {
+    fn open(path: &str, max_size: u64, keep: u32) -> Result<Self, types::AnyError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|why| format!("Couldn't open the log file {}: {}", path, why))?;
+        let size = file.metadata()?.len();
+
+        Ok(Self { path: path.into(), file, size, max_size, keep })
+    }
+
+    fn write(&mut self, line: &str) -> Result<(), types::AnyError> {
+        let len = line.len() as u64 + 1;
+
+        if self.max_size != 0 && self.size != 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    // shifts file.N to file.N+1, dropping the oldest, and starts over with an empty file
+    fn rotate(&mut self) -> Result<(), types::AnyError> {
+        if self.keep == 0 {
+            self.file.set_len(0)?;
+            self.size = 0;
+            return Ok(());
+        }
+
+        for n in (1..self.keep).rev() {
+            // missing ones are fine, there might not have been that many rotations yet
+            let _ = fs::rename(format!("{}.{}", self.path, n), format!("{}.{}", self.path, n + 1));
+        }
+
+        fs::rename(&self.path, format!("{}.1", self.path))?;
+        *self = Self::open(&self.path, self.max_size, self.keep)?;
+        Ok(())
+    }
+}
+
+// rfc 3339 in utc with millis
+fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards");
+
+    let secs = now.as_secs();
+    let (year, month, day) = civil(secs / 86400);
+    let rem = secs % 86400;
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60, now.subsec_millis())
+}
+
+// days since the unix epoch to (year, month, day) in the proleptic gregorian calendar
+pub(super) fn civil(days: u64) -> (u64, u64, u64) {
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
@@ -0,0 +1,105 @@
+// prometheus metrics, kept in one registry and rendered by `GET /metrics`. the
+// proxy checker, the rate limit updater and the api record as they go, the pool
+// and rate limit gauges are set from the database on every scrape.
+//
+// probe latency is labelled by target, not by proxy: a label per proxy would make a
+// series for every proxy that was ever checked. the latency of a single proxy is in
+// its check history, see `GET /proxies/<id>/history`.
+
+// prometheus
+use prometheus::{Encoder, TextEncoder, Registry, Opts, HistogramOpts};
+use prometheus::{Histogram, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec};
+
+// once_cell
+use once_cell::sync::Lazy;
+
+// super
+use super::types;
+
+// secs, from a quick pass over a few proxies to a whole cycle-budget
+const CYCLE_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+pub struct Metrics {
+    registry: Registry,
+
+    // pool
+    pub proxies: IntGaugeVec, // by state: live, blacklisted, ratelimited (live with an active entry)
+    pub proxies_by_schema: IntGaugeVec, // live ones
+    pub ratelimits: IntGaugeVec, // active entries by website
+
+    // proxy checker
+    pub check_cycles: HistogramVec, // by kind: live, blacklisted
+    pub probes: IntCounterVec, // by result: passed or the error class
+    pub probe_latency: HistogramVec, // by target url, see above
+
+    // rate limit updater
+    pub ratelimit_updates: Histogram,
+    pub ratelimits_expired: IntCounter,
+
+    // api
+    pub requests: IntCounterVec, // by method, route and status
+    pub request_latency: HistogramVec, // same
+    pub handouts: IntCounterVec, // proxies handed out by manager key id
+}
+
+macro_rules! register {
+    ($registry:expr, $metric:expr) => {
+        {
+            let metric = $metric.expect("Invalid metric");
+            $registry.register(Box::new(metric.clone())).expect("Metric registered twice");
+            metric
+        }
+    };
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("kildin".into()), None).expect("Invalid registry");
+        let histogram = |name: &str, help: &str| HistogramOpts::new(name, help);
+
+        Self {
+            proxies: register!(registry, IntGaugeVec::new(
+                Opts::new("proxies", "Proxies in the pool by state"), &["state"])),
+            proxies_by_schema: register!(registry, IntGaugeVec::new(
+                Opts::new("proxies_by_schema", "Live proxies by schema"), &["schema"])),
+            ratelimits: register!(registry, IntGaugeVec::new(
+                Opts::new("ratelimits", "Active rate limit entries by website"), &["website"])),
+
+            check_cycles: register!(registry, HistogramVec::new(
+                histogram("check_cycle_seconds", "Duration of proxy checker cycles")
+                    .buckets(CYCLE_BUCKETS.to_vec()), &["kind"])),
+            probes: register!(registry, IntCounterVec::new(
+                Opts::new("probes_total", "Probes of the proxy checker by result"), &["result"])),
+            probe_latency: register!(registry, HistogramVec::new(
+                histogram("probe_latency_seconds", "Response time of the targets through the proxies"), &["target"])),
+
+            ratelimit_updates: register!(registry, Histogram::with_opts(
+                histogram("ratelimit_update_seconds", "Duration of rate limit updater passes")
+                    .buckets(CYCLE_BUCKETS.to_vec()))),
+            ratelimits_expired: register!(registry, IntCounter::new(
+                "ratelimits_expired_total", "Rate limit entries removed after they expired")),
+
+            requests: register!(registry, IntCounterVec::new(
+                Opts::new("requests_total", "API requests"), &["method", "route", "status"])),
+            request_latency: register!(registry, HistogramVec::new(
+                histogram("request_seconds", "API response time"), &["method", "route", "status"])),
+            handouts: register!(registry, IntCounterVec::new(
+                Opts::new("handouts_total", "Proxies handed out by manager"), &["key_id"])),
+
+            registry
+        }
+    }
+
+    // the text exposition format
+    pub fn render(&self) -> Result<String, types::AnyError> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
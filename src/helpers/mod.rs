@@ -1,3 +1,8 @@
 pub mod config;
 pub mod types;
-pub mod logger;
\ No newline at end of file
+pub mod logger;
+pub mod random;
+pub mod metrics;
+
+#[cfg(test)]
+mod tests;
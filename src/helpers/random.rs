@@ -0,0 +1,14 @@
+// std
+use std::iter;
+
+// rand
+use rand::distributions::Alphanumeric;
+use rand::prelude::*;
+
+pub fn random_string(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+
+    iter::repeat(())
+        .map(|()| rng.sample(Alphanumeric))
+        .take(len).collect()
+}
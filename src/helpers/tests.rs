@@ -0,0 +1,205 @@
+// config layering and parsing and the logger, without a database
+
+// crate
+use crate::helpers::config::{Config, Kind, LoggingSettings, KEYS, BACKGROUND_CONNECTIONS, CHECKER_IDLE, MAINTENANCE_INTERVAL};
+use crate::helpers::logger::{civil, Level, LogFormat, Logger};
+use crate::helpers::random::random_string;
+use crate::helpers::types;
+
+// toml
+use toml::Value;
+
+// std
+use std::env;
+use std::fs;
+
+fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+    vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>().into_iter()
+}
+
+fn set(path: &str, raw: &str) -> (String, String) {
+    (path.to_string(), raw.to_string())
+}
+
+#[test]
+fn layering() {
+    let file = "[general]\ndatabase-path = \"file.db\"\npool-size = 8\n[server]\nworkers = 2\n[proxy-checker-settings]\ntimeout = 20\n";
+
+    // defaults < file
+    let config = Config::from(file).unwrap();
+    assert_eq!(config.general.database_path, "file.db");
+    assert_eq!(config.general.pool_size, 8);
+    assert_eq!(config.general.requests_per_minute, 60);
+    assert_eq!(Config::from("").unwrap().proxy_settings.timeout, 10);
+
+    // file < environment < flags, the last override of a key wins
+    let (mut overrides, warnings) = Config::overrides_from(vars(&[
+        ("KILDIN_PROXY_CHECKER_SETTINGS_TIMEOUT", "5"),
+        ("KILDIN_GENERAL_POOL_SIZE", "12")
+    ]));
+    assert!(warnings.is_empty());
+    overrides.push(set("proxy-checker-settings.timeout", "7"));
+
+    let config = Config::layered(file, &overrides).unwrap();
+    assert_eq!(config.proxy_settings.timeout, 7);
+    assert_eq!(config.general.pool_size, 12);
+    assert_eq!(config.general.database_path, "file.db");
+
+    // the pool has a connection for every worker and the background threads, an omitted pool-size grows with them
+    assert!(Config::layered(file, &[set("server.workers", "3")]).is_ok());
+    assert!(Config::layered(file, &[set("server.workers", "4")]).is_err());
+    assert!(Config::layered(file, &[set("server.workers", "4"), set("general.pool-size", "9")]).is_ok());
+    let config = Config::layered("", &[set("server.workers", "40")]).unwrap();
+    assert_eq!(config.general.pool_size, 40 + BACKGROUND_CONNECTIONS);
+    assert_eq!(Config::layered("", &[set("server.workers", "1")]).unwrap().general.pool_size, 16);
+
+    // a pass may take two cycle-budgets after the maintenance interval and the idle wait
+    let staleness = |budget: u64, staleness: u64| Config::layered(file, &[
+        set("proxy-checker-settings.cycle-budget", &budget.to_string()),
+        set("server.ready-max-staleness", &staleness.to_string())
+    ]);
+    assert!(staleness(100, 200 + MAINTENANCE_INTERVAL + CHECKER_IDLE).is_ok());
+    assert!(staleness(100, 199 + MAINTENANCE_INTERVAL + CHECKER_IDLE).is_err());
+    let config = Config::from(file).unwrap();
+    assert!(config.server.ready_max_staleness >= config.min_ready_staleness());
+
+    // overrides are validated like the file
+    assert!(Config::layered(file, &[set("proxy-checker-settings.timeout", "0")]).is_err());
+    assert!(Config::layered(file, &[set("proxy-checker-settings.timeout", "soon")]).is_err());
+    assert!(Config::layered(file, &[set("general.unknown", "1")]).is_err());
+    assert!(Config::layered(file, &[set("unknown.timeout", "1")]).is_err());
+}
+
+#[test]
+fn env_parsing() {
+    let (overrides, warnings) = Config::overrides_from(vars(&[
+        ("PATH", "/usr/bin"),
+        ("KILDIN_GENERAL_DATABASE_PATH", "123"),
+        ("KILDIN_PROXY_CHECKER_SETTINGS_MAX_FAILS", "5"),
+        ("KILDIN_SERVER_ADDRESS", "0.0.0.0"),
+        ("KILDIN_HOME", "/opt/kildin"),
+        ("KILDIN_GENERAL_UNKNOWN", "1"),
+        ("KILDIN_GENERAL_", "1")
+    ]));
+
+    assert_eq!(overrides, vec![
+        set("general.database-path", "123"),
+        set("proxy-checker-settings.max-fails", "5"),
+        set("server.address", "0.0.0.0")
+    ]);
+    assert_eq!(warnings.len(), 3);
+    assert!(warnings[0].starts_with("KILDIN_HOME"));
+
+    // the key's type decides, not what the value looks like
+    let config = Config::layered("", &overrides).unwrap();
+    assert_eq!(config.general.database_path, "123");
+    assert_eq!(config.proxy_settings.max_fails, 5);
+    assert_eq!(Config::parse_value(Kind::Text, "1.5"), Ok(Value::String("1.5".into())));
+    assert_eq!(Config::parse_value(Kind::Integer, "15"), Ok(Value::Integer(15)));
+    assert!(Config::parse_value(Kind::Integer, "true").is_err());
+}
+
+#[test]
+fn override_keys() {
+    // every key can be overridden, and every key of a section is listed
+    for (section, key, kind) in KEYS.iter() {
+        let raw = match kind { Kind::Text => "text", Kind::Integer => "1" };
+        let toml = format!("[{}]\n{} = {}", section, key, Config::parse_value(*kind, raw).unwrap());
+        let res = toml::from_str::<Value>(&toml).unwrap().try_into::<Config>();
+
+        if let Err(why) = res {
+            assert!(!why.to_string().contains("unknown field"), "{}.{}: {}", section, key, why);
+        }
+    }
+
+    for section in ["general", "proxy-checker-settings", "server", "logging"].iter() {
+        let why = toml::from_str::<Value>(&format!("[{}]\nnot-a-key = 1", section)).unwrap()
+            .try_into::<Config>().err().unwrap().to_string();
+
+        // "unknown field `not-a-key`, expected one of `a`, `b` for key `section`"
+        let expected = why.split("expected one of ").nth(1).unwrap().split(" for key").next().unwrap();
+        let mut fields = expected.split('`').skip(1).step_by(2).filter(|f| *f != "targets").collect::<Vec<_>>();
+        let mut keys = KEYS.iter().filter(|(s, _, _)| s == section).map(|(_, k, _)| *k).collect::<Vec<_>>();
+        fields.sort();
+        keys.sort();
+        assert_eq!(fields, keys, "{}", why);
+    }
+}
+
+#[test]
+fn deprecated_keys() {
+    let config = Config::from("[proxy-checker-settings]\nhead-loc = \"https://duckduckgo.com/\"").unwrap();
+    assert_eq!(config.warnings.len(), 1);
+    assert!(Config::from("").unwrap().warnings.is_empty());
+}
+
+// a json logger writing to a new file that rotates after every line
+fn log_file(keep: u32) -> (String, Logger) {
+    let path = env::temp_dir().join(format!("kildin-test-{}.log", random_string(12))).to_string_lossy().into_owned();
+    let settings = LoggingSettings { level: Level::Info, format: LogFormat::Json, file: Some(path.clone()), max_size: 100, keep };
+    (path, Logger::open(&settings).unwrap())
+}
+
+fn messages(path: &str) -> Vec<String> {
+    fs::read_to_string(path).unwrap_or_default().lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["message"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn log_rotation() {
+    let (path, logger) = log_file(2);
+
+    for n in 0..4 {
+        logger.log(Level::Info, &format!("line {}", n));
+    }
+
+    // the oldest line is dropped with the file past `keep`
+    assert_eq!(messages(&path), vec!["line 3"]);
+    assert_eq!(messages(&format!("{}.1", path)), vec!["line 2"]);
+    assert_eq!(messages(&format!("{}.2", path)), vec!["line 1"]);
+    assert!(fs::metadata(format!("{}.3", path)).is_err());
+
+    // with nothing to keep the file is truncated instead
+    let (truncated, logger) = log_file(0);
+    logger.log(Level::Info, "first");
+    logger.log(Level::Info, "second");
+    logger.log(Level::Debug, "below the level");
+
+    assert_eq!(messages(&truncated), vec!["second"]);
+    assert!(fs::metadata(format!("{}.1", truncated)).is_err());
+
+    for file in [path.clone(), format!("{}.1", path), format!("{}.2", path), truncated].iter() {
+        let _ = fs::remove_file(file);
+    }
+}
+
+#[test]
+fn log_fields() {
+    let (path, logger) = log_file(0);
+    let why: types::AnyError = "no connection".into();
+    logger.log_with(Level::Warn, "fields", &[
+        ("proxy_id", &42), ("rating", &7.5), ("blacklisted", &true), ("website", &"https://a.org/"), ("error", &why)
+    ]);
+
+    let line = serde_json::from_str::<serde_json::Value>(fs::read_to_string(&path).unwrap().trim()).unwrap();
+    assert_eq!(line["level"], "warn");
+    assert_eq!(line["proxy_id"], 42);
+    assert_eq!(line["rating"], 7.5);
+    assert_eq!(line["blacklisted"], true);
+    assert_eq!(line["website"], "https://a.org/");
+    assert_eq!(line["error"], "no connection");
+
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn civil_dates() {
+    assert_eq!(civil(0), (1970, 1, 1));
+    assert_eq!(civil(59), (1970, 3, 1));
+    assert_eq!(civil(11016), (2000, 2, 29)); // a leap day of a century divisible by 400
+    assert_eq!(civil(11017), (2000, 3, 1));
+    assert_eq!(civil(19782), (2024, 2, 29));
+    assert_eq!(civil(20453), (2025, 12, 31));
+    assert_eq!(civil(47541), (2100, 3, 1)); // 2100 has no leap day
+}
@@ -7,45 +7,78 @@ pub mod database;
 pub mod server;
 pub mod proxy_checker;
 pub mod ratelimit_updater;
+pub mod admin;
+pub mod cli;
+pub mod commands;
+pub mod config_reloader;
+pub mod health;
 
 // crate
 use crate::helpers::logger::{Level, Logger};
-use crate::helpers::config::{Config, ProxyCheckerSettings};
+use crate::helpers::config::{Config, SharedSettings, CHECKER_IDLE, MAINTENANCE_INTERVAL};
 use crate::ratelimit_updater::RatelimitUpdater;
 use crate::proxy_checker::ProxyChecker;
-use crate::database::managers::ManagerAuth;
-use crate::database::ratelimited::RateLimited;
-use crate::database::proxies::Proxies;
+use crate::config_reloader::ConfigReloader;
+use crate::health::Health;
+use crate::database::index::ProxyIndex;
+use crate::database::pool::Pool;
 use crate::helpers::types;
 
 // std
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
-use std::env::args;
-use std::path::Path;
+use std::time::{Duration, Instant};
 use std::fs;
 
-// rusqlite
-use rusqlite::Connection;
-
-fn start_proxy_checker(pcs: ProxyCheckerSettings, logger: Logger) {
+// both threads read `settings` on every pass, so a reload applies without a restart,
+// and report finished passes to `health`
+fn start_proxy_checker(pool: Pool, index: Arc<ProxyIndex>, settings: SharedSettings, health: Arc<Health>, logger: Logger) {
     logger.log(Level::Info, "The proxy checker and rate limit updater are starting!");
 
     // create structs
-    let mut pc = ProxyChecker::new(pcs.clone(), logger.clone());
-    let mut ru = RatelimitUpdater::new(logger.clone());
-    let dur = Duration::from_secs(pcs.interval);
+    let mut pc = ProxyChecker::new(&pool, index.clone(), settings.clone(), logger.clone());
+    let mut ru = RatelimitUpdater::new(&pool, index, logger.clone());
 
     let cloned_logger = logger.clone();
+    let cloned_health = health.clone();
 
     thread::spawn(move || {
+        let mut maintained: Option<Instant> = None;
+
         loop {
             let logger = cloned_logger.clone();
 
-            match pc.update() {
-                Ok(b) => if b { logger.log(Level::Info, "Successfully checked health of/updated the proxies!") },
-                Err(why) => logger.log(Level::Error,
-                    &format!("ProxyChecker/Error: {}", why))
+            if maintained.map_or(true, |at| at.elapsed() >= Duration::from_secs(MAINTENANCE_INTERVAL)) {
+                maintained = Some(Instant::now());
+
+                // independent, a failing one mustn't hold the other back
+                if let Err(why) = pc.resurrect() {
+                    logger.log_with(Level::Error, "ProxyChecker: Checking the blacklisted proxies failed",
+                        &[("task", &"resurrect"), ("error", &why)]);
+                }
+
+                if let Err(why) = pc.prune_history() {
+                    logger.log_with(Level::Error, "ProxyChecker: Pruning the check history failed",
+                        &[("task", &"prune-history"), ("error", &why)]);
+                }
+            }
+
+            let checked = match pc.update() {
+                Ok(proxies) => {
+                    cloned_health.checker_passed();
+                    proxies.len()
+                },
+                Err(why) => {
+                    logger.log_with(Level::Error, "ProxyChecker: Checking the proxies failed",
+                        &[("task", &"check"), ("error", &why)]);
+                    0
+                }
+            };
+
+            if checked != 0 {
+                logger.log(Level::Info, "Successfully checked health of/updated the proxies!");
+            } else {
+                thread::sleep(Duration::from_secs(CHECKER_IDLE));
             }
         }
     });
@@ -55,50 +88,106 @@ fn start_proxy_checker(pcs: ProxyCheckerSettings, logger: Logger) {
             let logger = logger.clone();
 
             match ru.update() {
-                Ok(_) => logger.log(Level::Info, "Successfully checked up on the rate limited proxies!"),
-                Err(why) => logger.log(Level::Error,
-                    &format!("RatelimitUpdater/Error: {}", why))
+                Ok(_) => {
+                    health.ratelimits_passed();
+                    logger.log(Level::Info, "Successfully checked up on the rate limited proxies!")
+                },
+                Err(why) => logger.log_with(Level::Error, "RatelimitUpdater: Updating the rate limits failed",
+                    &[("task", &"ratelimits"), ("error", &why)])
             }
 
-            thread::sleep(dur);
+            let interval = settings.read().map(|pcs| pcs.interval).unwrap_or(60);
+            thread::sleep(Duration::from_secs(interval));
         }
     });
 }
 
-fn load_config() -> Config {
-    let args = args().collect::<Vec<String>>();
-    let contents = fs::read_to_string(args[1].clone()).expect("Something went wrong with the file");
-    Config::from(&contents).expect("Couldn't read config")
-}
+// defaults < config file < KILDIN_* environment variables < command line flags
+fn try_load_config() -> Result<Config, types::AnyError> {
+    let options = cli::options();
+
+    // the default config file is optional, one passed with --config isn't
+    let (path, contents) = match &options.config_path {
+        Some(path) => (path.as_str(), fs::read_to_string(path)
+            .map_err(|why| format!("Couldn't read {}: {}", path, why))?),
+        None => (cli::DEFAULT_CONFIG_PATH, fs::read_to_string(cli::DEFAULT_CONFIG_PATH).unwrap_or_default())
+    };
+
+    let (mut overrides, warnings) = Config::env_overrides();
+    overrides.extend(options.overrides.iter().cloned());
 
-fn connect_to_database() -> Result<Connection, types::AnyError> {
-    let config = load_config();
-    let conn = Connection::open(Path::new(&config.general.database_path))?;
-    Ok(conn)
+    if let Some(database) = &options.database_path {
+        overrides.push(("general.database-path".into(), database.clone()));
+    }
+
+    let mut config = Config::layered(&contents, &overrides)
+        .map_err(|why| format!("Invalid config {}: {}", path, why))?;
+
+    config.warnings.extend(warnings);
+    Ok(config)
 }
 
-fn setup_db() {
-    Proxies::create(connect_to_database().unwrap());
-    ManagerAuth::create(connect_to_database().unwrap());
-    RateLimited::create(connect_to_database().unwrap());
+// opens the database and brings it up to the latest schema version
+fn setup_db(config: &Config) -> Result<Pool, types::AnyError> {
+    let pool = Pool::open(&config.general)?;
+    pool.migrate()?;
+    Ok(pool)
 }
 
-fn main() {
-    let logger = Logger::new();
+fn serve() -> Result<(), types::AnyError> {
+    // the config decides where and how much is logged
+    let config = try_load_config()?;
+    let logger = Logger::open(&config.logging)?;
 
     // report launch
     logger.log(Level::Info, "Currently running: Kildin v1.0");
     logger.log(Level::Info, "Kildin is starting.");
 
+    for warning in config.warnings.iter() {
+        logger.log_with(Level::Warn, "Config: Check your configuration.", &[("warning", warning)]);
+    }
+
     // connect to database
-    let config = load_config();
-    setup_db(); // setup db in case it isn't properly created
+    let pool = setup_db(&config)?; // setup db in case it isn't properly created
     logger.log(Level::Info, "Database checked!");
 
+    // selection is served from memory, changes are written back in the background
+    let index = Arc::new(ProxyIndex::load(&pool, logger.clone())?);
+    ProxyIndex::write_behind(index.clone());
+    let live = index.live()?;
+    logger.log_with(Level::Info, &format!("Indexed {} live proxies!", live), &[("amount", &live)]);
+
     // start proxy checker
-    start_proxy_checker(config.proxy_settings, logger.clone());
+    let settings = config.proxy_settings.clone().shared();
+    let health = Arc::new(Health::new(&config.server));
+    start_proxy_checker(pool.clone(), index.clone(), settings.clone(), health.clone(), logger.clone());
     logger.log(Level::Info, "The proxy checker has been started!");
 
+    // reload the proxy checker settings when the config changes
+    let reloader = Arc::new(ConfigReloader::new(settings, logger.clone()));
+    ConfigReloader::watch(reloader.clone());
+
     // start server
-    server::start();
+    server::start(&config, pool, index, reloader, health, logger)
+}
+
+fn main() {
+    let matches = cli::parse();
+
+    let res = match matches.subcommand() {
+        ("check", _) => commands::check(),
+        ("import", Some(args)) => commands::import(args),
+        ("export", Some(args)) => commands::export(args),
+        ("db", Some(args)) => commands::db(args),
+        ("config", Some(args)) => commands::config(args),
+        ("admin", Some(args)) => try_load_config()
+            .and_then(|config| setup_db(&config))
+            .and_then(|pool| admin::run(&pool, args)),
+        _ => serve()
+    };
+
+    if let Err(why) = res {
+        eprintln!("{}", why);
+        std::process::exit(1);
+    }
 }
@@ -1,16 +1,42 @@
 // crate
-use crate::database::proxies::{Proxies, Proxy};
-use crate::helpers::config::ProxyCheckerSettings;
+use crate::database::proxies::{Proxy, ProxyStore};
+use crate::database::history::{self, HistoryStore, ProbeRecord};
+use crate::database::index::ProxyIndex;
+use crate::database::pool::Pool;
+use crate::helpers::config::{CheckTarget, ProxyCheckerSettings, SharedSettings};
+use crate::helpers::metrics::metrics;
 use crate::helpers::{logger::{Level, Logger}, types};
 
 // reqwest
-use reqwest::blocking::Client;
-use reqwest::Proxy as ReqProxy;
+use reqwest::{Client, Proxy as ReqProxy};
+use native_tls::TlsConnector;
+
+// futures
+use futures::{future, stream, FutureExt, StreamExt};
+use futures::future::{BoxFuture, Shared};
+
+// tokio
+use tokio::runtime::{Builder, Runtime};
 
 // std
-use std::thread;
-use std::time::Duration;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+mod tests;
+
+// secs a direct request to a target is trusted for, from when it was sent
+const CONTROL_TTL: u64 = 30;
+
+// error classes of a probe besides history::TARGET_DOWN
+const ERR_TIMEOUT: &str = "timeout";
+const ERR_CONNECT: &str = "connect";
+const ERR_REQUEST: &str = "request"; // any other failed request
+const ERR_STATUS: &str = "status"; // the status code isn't accepted
+const ERR_BODY: &str = "body"; // the body doesn't contain the text
+const ERR_PROXY_URL: &str = "proxy-url"; // no client could be built for the proxy
 
 macro_rules! proxy_log {
     ($logger:expr, $success:expr, $verb:expr, $amount:expr) => {
@@ -19,21 +45,60 @@ macro_rules! proxy_log {
             let verb = if $success { format!("{}d", $verb) } else { $verb.into() };
             let level = if $success { Level::Info } else { Level::Warn };
             let msg = format!("ProxyManager: {} {} {} proxies.", success, verb, $amount);
-            $logger.log(level, &msg);
+            $logger.log_with(level, &msg, &[("action", &$verb), ("amount", &$amount)]);
         }
     };
 }
 
+// Checks the live proxies that are due, at most `concurrency` at a time. A cycle
+// that runs out of its `cycle-budget` stops launching checks, the proxies it
+// didn't get to are due again for the next one. a cycle claims its proxies, so
+// instances sharing a database don't check the same ones.
+//
+// when a proxy is due depends on how it did:
+// - new proxies right away
+// - passed its last check, after check-interval
+// - failing, after retry-interval, doubled with every fail up to max-retry-interval
+// - blacklisted, after blacklisted-interval
+//
+// blacklisted proxies are checked by `resurrect`, they come back after passing
+// resurrect-after checks in a row and are deleted once they're past the retention.
+//
+// a check probes every target through the proxy. a failed probe only counts against
+// the proxy when a direct request to the target works, so a target that is down
+// isn't blamed on the proxies. the proxy passes when the targets it reached weigh
+// at least half of the targets that were up, and its rating is based on how fast
+// it reached them. a check with every target down changes nothing but the schedule.
+// every probe is recorded in the check history.
 pub struct ProxyChecker {
-    proxies: Proxies,
-    pcs: ProxyCheckerSettings,
+    pool: Pool,
+    proxies: Box<dyn ProxyStore>,
+    index: Arc<ProxyIndex>, // results are written through the index
+    settings: SharedSettings,
+    runtime: Runtime,
+    tls: TlsConnector, // shared by the clients, loading the root certificates is slow
+    clients: HashMap<String, Client>, // by proxy url, kept across cycles
+    timeout: u64, // the timeout `clients` were built with
     logger: Logger,
 }
 
 impl ProxyChecker {
-    pub fn new(pcs: ProxyCheckerSettings, logger: Logger) -> Self {
-        let proxies = Proxies::new().expect("Couldn't connect to database");
-        Self { proxies, pcs, logger }
+    pub fn new(pool: &Pool, index: Arc<ProxyIndex>, settings: SharedSettings, logger: Logger) -> Self {
+        let proxies = pool.proxies().expect("Couldn't connect to database");
+        let runtime = Builder::new()
+            .threaded_scheduler()
+            .enable_all()
+            .build()
+            .expect("Couldn't start the proxy checker runtime");
+        let tls = TlsConnector::new().expect("Couldn't set up TLS");
+
+        Self { pool: pool.clone(), proxies, index, settings, runtime, tls, clients: HashMap::new(), timeout: 0, logger }
+    }
+
+    // a snapshot of the current settings, a reload takes effect on the next update
+    fn settings(&self) -> Result<ProxyCheckerSettings, types::AnyError> {
+        let pcs = self.settings.read().map_err(|_| "Settings lock is poisoned")?;
+        Ok(pcs.clone())
     }
 
     fn now() -> u64 {
@@ -44,103 +109,442 @@ impl ProxyChecker {
         dur.as_secs()
     }
 
-    fn check_proxy(pcs: ProxyCheckerSettings, proxy: &(u32, Proxy))
-        -> Result<Proxy, reqwest::Error>
+    // claims the due proxies for one cycle. the ones the cycle doesn't get to are due
+    // again once their lease of a cycle-budget runs out, around the start of the next one
+    fn entries(&mut self, blacklisted: bool, pcs: &ProxyCheckerSettings) -> Result<Vec<(u32, Proxy)>, types::AnyError> {
+        let now = Self::now();
+        let lease_until = now + pcs.cycle_budget;
+        let mut entries = Vec::new();
+
+        loop {
+            let page = self.proxies.claim(blacklisted, now, lease_until, pcs.pagination)?;
+
+            if page.len() == 0 {
+                break;
+            }
+
+            entries.extend(page);
+        }
+
+        Ok(entries)
+    }
+
+    // seconds until the next check, see the comment on `ProxyChecker`
+    fn interval(pcs: &ProxyCheckerSettings, proxy: &Proxy, reachable: bool) -> u64 {
+        // a blacklisted proxy that passed is checked as often as a live one until it's back
+        if reachable {
+            return pcs.check_interval;
+        }
+
+        if proxy.blacklisted {
+            return pcs.blacklisted_interval;
+        }
+
+        let doublings = proxy.fails.saturating_sub(1).min(32);
+        pcs.retry_interval.saturating_mul(1 << doublings).min(pcs.max_retry_interval)
+    }
+
+    // reuses the client of a proxy so its connections are kept alive, drops the
+    // clients of proxies that are gone. `None` if the proxy url is unusable.
+    fn clients(&mut self, entries: &[(u32, Proxy)], timeout: u64) -> Vec<Option<Client>> {
+        if self.timeout != timeout {
+            self.clients.clear();
+            self.timeout = timeout;
+        }
+
+        let mut cached = mem::take(&mut self.clients);
+        let mut clients = HashMap::new();
+        let tls = &self.tls;
+
+        let list = entries.iter().map(|(_, proxy)| {
+            let url = proxy.url();
+            let client = match cached.remove(&url) {
+                Some(client) => Some(client),
+                None => ReqProxy::all(&url)
+                    .and_then(|p| Client::builder()
+                        .use_preconfigured_tls(tls.clone())
+                        .proxy(p)
+                        .timeout(Duration::from_secs(timeout))
+                        .build())
+                    .ok()
+            };
+
+            if let Some(client) = &client {
+                clients.insert(url, client.clone());
+            }
+
+            client
+        }).collect();
+
+        self.clients = clients;
+        list
+    }
+
+    fn classify(why: &reqwest::Error) -> &'static str {
+        if why.is_timeout() {
+            ERR_TIMEOUT
+        } else if why.is_connect() {
+            ERR_CONNECT
+        } else {
+            ERR_REQUEST
+        }
+    }
+
+    // requests `target` through `client`
+    async fn attempt(client: &Client, target: &CheckTarget) -> Attempt {
+        let before = Instant::now();
+        let request = match target.contains {
+            Some(_) => client.get(&target.url),
+            None => client.head(&target.url)
+        };
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(why) => return Attempt { latency: None, status: None, error: Some(Self::classify(&why)) }
+        };
+
+        let latency = Some(before.elapsed());
+        let status = response.status().as_u16();
+
+        if target.status.len() != 0 && !target.status.contains(&status) {
+            return Attempt { latency, status: Some(status), error: Some(ERR_STATUS) };
+        }
+
+        let error = match &target.contains {
+            Some(text) => match response.text().await {
+                Ok(body) if body.contains(text.as_str()) => None,
+                Ok(_) => Some(ERR_BODY),
+                Err(why) => Some(Self::classify(&why))
+            },
+            None => None
+        };
+
+        Attempt { latency, status: Some(status), error }
+    }
+
+    // a failed attempt is put on the target if it's down without the proxy too
+    async fn probe(control: &Control, client: Option<&Client>, target: &CheckTarget) -> Attempt {
+        let mut attempt = match client {
+            Some(client) => Self::attempt(client, target).await,
+            None => Attempt { latency: None, status: None, error: Some(ERR_PROXY_URL) }
+        };
+
+        if attempt.error.is_some() && !control.up(target).await {
+            attempt.error = Some(history::TARGET_DOWN);
+        }
+
+        attempt
+    }
+
+    async fn check_proxy(pcs: &ProxyCheckerSettings, targets: &[CheckTarget], control: &Control,
+        id: u32, proxy: Proxy, client: Option<Client>) -> (Proxy, Vec<ProbeRecord>)
     {
-        let dur = Duration::from_secs(pcs.timeout);
-        let before = Self::now();
+        let attempts = future::join_all(targets.iter().map(|t| Self::probe(control, client.as_ref(), t))).await;
+        let now = Self::now();
+        let (passed, counted, secs) = Self::tally(targets, &attempts);
 
-        // construct client
-        let proxy_addr = format!("{}://{}:{}", proxy.1.schema, proxy.1.address, proxy.1.port);
-        let client = Client::builder()
-            .proxy(ReqProxy::https(&proxy_addr)?)
-            .timeout(dur).build()?;
+        let records = targets.iter().zip(attempts).map(|(target, attempt)| ProbeRecord {
+            proxy_id: id,
+            timestamp: now,
+            target: target.url.clone(),
+            latency_ms: attempt.latency.map(|l| l.as_millis() as u32),
+            status: attempt.status,
+            error: attempt.error.map(String::from)
+        });
+
+        (Self::judge(pcs, proxy, now, passed, counted, secs), records.collect())
+    }
 
-        // send req and calculate score
-        client.head(&pcs.dest).send()?;
+    // the weight of the targets the proxy reached and of the ones that count, targets
+    // that are down don't. also the weighted sum of the response times in secs.
+    fn tally(targets: &[CheckTarget], attempts: &[Attempt]) -> (f64, f64, f64) {
+        let mut passed = 0.0;
+        let mut counted = 0.0;
+        let mut secs = 0.0;
 
-        // score
-        let new_score = (dur.as_secs() - (Self::now() - before)) as f64;
-        let score = (new_score + proxy.1.rating) / 2.0;
+        for (target, attempt) in targets.iter().zip(attempts.iter()) {
+            match attempt.error {
+                None => {
+                    passed += target.weight;
+                    counted += target.weight;
+                    secs += target.weight * attempt.latency.map_or(0.0, |l| l.as_secs_f64());
+                },
+                Some(history::TARGET_DOWN) => (),
+                Some(_) => counted += target.weight
+            }
+        }
 
-        Ok(Proxy {
-            schema: proxy.1.schema.clone(),
-            address: proxy.1.address.clone(),
-            port: proxy.1.port.clone(),
-            rating: if score > 10.0 { 10.0 } else { score },
-            fails: proxy.1.fails,
-            blacklisted: false
-        })
+        (passed, counted, secs)
     }
 
-    pub fn update(&mut self) -> Result<bool, types::AnyError> {
-        let mut idx = 0;
+    // applies the outcome of a check, see the comment on `ProxyChecker`
+    fn judge(pcs: &ProxyCheckerSettings, mut proxy: Proxy, now: u64, passed: f64, counted: f64, secs: f64) -> Proxy {
+        if counted == 0.0 {
+            proxy.next_check_at = now + pcs.retry_interval;
+            return proxy;
+        }
 
-        loop {
-            let mut proxies = Vec::new();
-            let mut handles = Vec::new();
-            let entries: _ = self.proxies.after(idx, self.pcs.pagination)?;
+        let reachable = passed != 0.0 && passed * 2.0 >= counted;
 
-            if entries.len() == 0 {
-                return Ok(false);
+        if reachable {
+            // the faster the responses and the more targets reached, the higher the score
+            let score = (pcs.timeout as f64 - secs / passed).max(0.0) * passed / counted;
+            proxy.rating = ((score + proxy.rating) / 2.0).min(10.0);
+        }
+
+        if proxy.blacklisted {
+            // a fail starts over
+            proxy.successes = if reachable { proxy.successes + 1 } else { 0 };
+
+            if proxy.successes >= pcs.resurrect_after {
+                proxy.blacklisted = false;
+                proxy.blacklisted_at = None;
+                proxy.successes = 0;
+                proxy.fails = 0;
+            }
+        } else {
+            if !reachable {
+                proxy.fails += 1;
+            } else if proxy.rating != 0.0 {
+                proxy.fails = proxy.fails.saturating_sub(1);
             }
 
-            // set idx
-            idx = entries[entries.len() - 1].0;
+            if proxy.fails >= pcs.max_fails {
+                proxy.blacklisted = true;
+                proxy.blacklisted_at = Some(now);
+            }
+        }
 
-            // launch threads
-            for proxy in entries.clone() {
-                if proxy.1.blacklisted {
-                    continue;
-                }
+        proxy.next_check_at = now + Self::interval(pcs, &proxy, reachable);
+        proxy
+    }
 
-                let pcs = self.pcs.clone();
-                let handle: _ = thread::spawn(move || Self::check_proxy(pcs, &proxy));
-                handles.push(handle);
+    // runs the checks until they're done or the deadline passed, results are
+    // written in batches of `pagination`. returns the checked proxies.
+    async fn run(pcs: &ProxyCheckerSettings, jobs: Vec<(u32, Proxy, Option<Client>)>, deadline: Instant,
+        control: &Control, sink: &mut Sink<'_>) -> Vec<Proxy>
+    {
+        let mut checked = Vec::new();
+        let mut batch = Vec::new();
+        let targets = pcs.targets();
+
+        let mut results = stream::iter(jobs)
+            .take_while(|_| future::ready(Instant::now() < deadline))
+            .map(|(id, proxy, client)| Self::check_proxy(pcs, &targets, control, id, proxy, client))
+            .buffer_unordered(pcs.concurrency);
+
+        while let Some(result) = results.next().await {
+            batch.push(result);
+
+            if batch.len() >= pcs.pagination as usize {
+                checked.extend(batch.iter().map(|r| r.0.clone()));
+                sink.write(batch.split_off(0));
             }
+        }
+
+        if batch.len() != 0 {
+            checked.extend(batch.iter().map(|r| r.0.clone()));
+            sink.write(batch);
+        }
+
+        checked
+    }
+
+    // checks the live or blacklisted proxies that are due, returns them
+    fn cycle(&mut self, pcs: &ProxyCheckerSettings, blacklisted: bool) -> Result<Vec<Proxy>, types::AnyError> {
+        let started = Instant::now();
+        let deadline = started + Duration::from_secs(pcs.cycle_budget);
+
+        let entries = self.entries(blacklisted, pcs)?;
+        let clients = self.clients(&entries, pcs.timeout);
+        let total = entries.len();
+
+        let jobs = entries.into_iter()
+            .zip(clients)
+            .map(|((id, proxy), client)| (id, proxy, client))
+            .collect::<Vec<_>>();
 
-            // wait for threads to finish
-            for handle in handles {
-                let res = handle.join();
+        let index = self.index.clone();
+        let logger = self.logger.clone();
+        let control = Control::new(&self.tls, pcs.timeout, logger.clone());
+        let mut sink = Sink { index: &index, history: self.pool.history()?, logger: &logger, blacklisted };
+        let checked = self.runtime.block_on(Self::run(pcs, jobs, deadline, &control, &mut sink));
+        drop(sink); // returns the history connection before the flush takes two more
 
-                if res.is_err() {
-                    continue;
-                }
+        // the next cycle finds the due proxies in the database, it mustn't see the old schedule
+        self.index.flush()?;
 
-                if let Ok(proxy) = res.unwrap() {
-                    let mut proxy = proxy.clone();
+        if total != 0 {
+            let kind = if blacklisted { "blacklisted" } else { "live" };
+            metrics().check_cycles.with_label_values(&[kind]).observe(started.elapsed().as_secs_f64());
+        }
+
+        if checked.len() < total {
+            let msg = format!("ProxyChecker: Ran out of time after {} of {} due proxies, the rest are due again.", checked.len(), total);
+            self.logger.log_with(Level::Warn, &msg, &[("checked", &checked.len()), ("due", &total)]);
+        }
 
-                    if proxy.rating != 0.0 {
-                        proxy.fails -= 1;
-                    }
+        Ok(checked)
+    }
 
-                    proxies.push(proxy);
-                }
+    // returns the checked proxies, empty if there was nothing to check
+    pub fn update(&mut self) -> Result<Vec<Proxy>, types::AnyError> {
+        let pcs = self.settings()?;
+        self.cycle(&pcs, false)
+    }
+
+    // checks the blacklisted proxies that are due and deletes the ones that were
+    // blacklisted longer than the retention. returns the checked proxies.
+    pub fn resurrect(&mut self) -> Result<Vec<Proxy>, types::AnyError> {
+        let pcs = self.settings()?;
+
+        if pcs.retention != 0 {
+            let purged = self.proxies.purge(Self::now().saturating_sub(pcs.retention))?;
+
+            if purged != 0 {
+                let msg = format!("ProxyChecker: Deleted {} proxies that were blacklisted for longer than the retention.", purged);
+                self.logger.log_with(Level::Info, &msg, &[("action", &"purge"), ("amount", &purged)]);
             }
+        }
+
+        let checked = self.cycle(&pcs, true)?;
+        let revived = checked.iter().filter(|p| !p.blacklisted).count();
+
+        if revived != 0 {
+            let msg = format!("ProxyChecker: Resurrected {} proxies.", revived);
+            self.logger.log_with(Level::Info, &msg, &[("action", &"resurrect"), ("amount", &revived)]);
+        }
+
+        Ok(checked)
+    }
+
+    // deletes the probes and rollups past their retention, returns how many rows
+    pub fn prune_history(&mut self) -> Result<usize, types::AnyError> {
+        let pcs = self.settings()?;
+        let now = Self::now();
+        self.pool.history()?.prune(now, now.saturating_sub(pcs.history_retention))
+    }
+}
+
+// a request to a target
+struct Attempt {
+    latency: Option<Duration>, // until the response, none without one
+    status: Option<u16>,
+    error: Option<&'static str> // none if the response was accepted
+}
 
-            for (_, entry) in entries {
-                let e = entry.clone();
-                let inner_proxies = proxies.iter()
-                    .map(|p| (p.address.clone(), p.port))
-                    .collect::<Vec<(String, u16)>>();
-
-                if !inner_proxies.contains(&(e.address, e.port)) {
-                    let mut proxy = entry.clone();
-                    proxy.fails += 1;
-                    proxies.push(proxy);
-                }
+// where the results of a cycle are written
+struct Sink<'a> {
+    index: &'a ProxyIndex,
+    history: Box<dyn HistoryStore>,
+    logger: &'a Logger,
+    blacklisted: bool // what the proxies were before they were checked
+}
+
+impl Sink<'_> {
+    fn write(&mut self, results: Vec<(Proxy, Vec<ProbeRecord>)>) {
+        let (proxies, probes): (Vec<Proxy>, Vec<Vec<ProbeRecord>>) = results.into_iter().unzip();
+        let amount = proxies.len();
+
+        if self.logger.enabled(Level::Debug) {
+            for (proxy, records) in proxies.iter().zip(probes.iter()) {
+                let id = records.first().map_or(0, |r| r.proxy_id);
+                let passed = records.iter().filter(|r| r.error.is_none()).count();
+
+                self.logger.log_with(Level::Debug, "ProxyChecker: Checked a proxy", &[
+                    ("proxy_id", &id), ("proxy", &proxy.url()), ("passed", &passed), ("probes", &records.len()),
+                    ("rating", &proxy.rating), ("fails", &proxy.fails), ("blacklisted", &proxy.blacklisted)
+                ]);
             }
+        }
+
+        let success = if self.blacklisted {
+            self.index.update_blacklisted(proxies).is_ok()
+        } else {
+            self.index.update_proxies(proxies).is_ok()
+        };
+
+        proxy_log!(self.logger, success, "update", amount);
+
+        for probe in probes.iter().flatten() {
+            metrics().probes.with_label_values(&[probe.error.as_deref().unwrap_or("passed")]).inc();
 
-            for (idx, proxy) in proxies.clone().iter().enumerate() {
-                proxies[idx].blacklisted = proxy.fails >= self.pcs.max_fails;
+            if let Some(ms) = probe.latency_ms {
+                metrics().probe_latency.with_label_values(&[&probe.target]).observe(ms as f64 / 1000.0);
             }
+        }
+
+        let probes = probes.concat();
+
+        if let Err(why) = self.history.record(&probes) {
+            self.logger.log_with(Level::Warn, "ProxyChecker: Couldn't record the probes in the history.",
+                &[("amount", &probes.len()), ("error", &why)]);
+        }
+    }
+}
+
+// whether a target answered a direct request
+type Request = Shared<BoxFuture<'static, bool>>;
 
-            // try to update proxies
-            let amount = proxies.len();
-            let success = self.proxies.update_proxies(proxies).is_ok();
-            proxy_log!(self.logger, success, "update", amount);
-            
-            return Ok(true);
+// direct requests to the targets, a request is reused for CONTROL_TTL. probes that
+// ask while it's in flight wait for the same request.
+struct Control {
+    client: Option<Client>, // `None` if it couldn't be built, every target counts as up
+    requests: Mutex<HashMap<String, (Instant, Request)>>, // by url, with when it was sent
+    logger: Logger
+}
+
+impl Control {
+    fn new(tls: &TlsConnector, timeout: u64, logger: Logger) -> Self {
+        let client = Client::builder()
+            .use_preconfigured_tls(tls.clone())
+            .no_proxy()
+            .timeout(Duration::from_secs(timeout))
+            .build()
+            .ok();
+
+        Self { client, requests: Mutex::new(HashMap::new()), logger }
+    }
+
+    fn send(client: &Client, target: &CheckTarget) -> Request {
+        let (client, target) = (client.clone(), target.clone());
+        async move { ProxyChecker::attempt(&client, &target).await.error.is_none() }.boxed().shared()
+    }
+
+    // the request for `target` that is still trusted or a new one. with a new one, whether
+    // the target was up the time before (or `true` if it isn't known), for the log.
+    fn request(&self, client: &Client, target: &CheckTarget) -> (Request, Option<bool>) {
+        let mut requests = match self.requests.lock() {
+            Ok(requests) => requests,
+            Err(_) => return (Self::send(client, target), Some(true))
+        };
+
+        let was_up = match requests.get(&target.url) {
+            Some((sent, request)) if sent.elapsed() < Duration::from_secs(CONTROL_TTL) => return (request.clone(), None),
+            Some((_, request)) => request.peek().cloned().unwrap_or(true),
+            None => true
+        };
+
+        let request = Self::send(client, target);
+        requests.insert(target.url.clone(), (Instant::now(), request.clone()));
+        (request, Some(was_up))
+    }
+
+    async fn up(&self, target: &CheckTarget) -> bool {
+        let client = match &self.client {
+            Some(client) => client,
+            None => return true
+        };
+
+        let (request, was_up) = self.request(client, target);
+        let up = request.await;
+
+        // only the probe that sent the request logs
+        if !up && was_up == Some(true) {
+            let msg = format!("ProxyChecker: {} can't be reached without a proxy either, its fails aren't counted.", target.url);
+            self.logger.log_with(Level::Warn, &msg, &[("target", &target.url)]);
         }
+
+        up
     }
 }
@@ -0,0 +1,117 @@
+// scheduling and judging of checks, without a database or network
+
+// crate
+use crate::proxy_checker::{Attempt, ProxyChecker, ERR_TIMEOUT};
+use crate::database::history::TARGET_DOWN;
+use crate::database::proxies::Proxy;
+use crate::helpers::config::{CheckTarget, ProxyCheckerSettings};
+
+// std
+use std::time::Duration;
+
+fn proxy(fails: u32, blacklisted: bool) -> Proxy {
+    Proxy {
+        schema: "http".into(),
+        address: "1.1.1.1".into(),
+        port: 80,
+        rating: 5.0,
+        fails, blacklisted,
+        next_check_at: 0,
+        successes: 0,
+        blacklisted_at: None
+    }
+}
+
+fn target(weight: f64) -> CheckTarget {
+    CheckTarget { url: format!("https://{}.org/", weight), weight, status: Vec::new(), contains: None }
+}
+
+fn attempt(error: Option<&'static str>) -> Attempt {
+    Attempt { latency: error.map_or(Some(Duration::from_secs(2)), |_| None), status: None, error }
+}
+
+#[test]
+fn backoff() {
+    let pcs = ProxyCheckerSettings { retry_interval: 60, max_retry_interval: 3600, ..Default::default() };
+    let interval = |fails: u32| ProxyChecker::interval(&pcs, &proxy(fails, false), false);
+
+    // doubled with every fail after the first
+    assert_eq!(interval(0), 60);
+    assert_eq!(interval(1), 60);
+    assert_eq!(interval(2), 120);
+    assert_eq!(interval(3), 240);
+    assert_eq!(interval(6), 1920);
+
+    // up to the cap, without overflowing on many fails
+    assert_eq!(interval(7), 3600);
+    assert_eq!(interval(40), 3600);
+    assert_eq!(interval(u32::MAX), 3600);
+
+    let pcs = ProxyCheckerSettings { max_retry_interval: u64::MAX, ..pcs };
+    assert_eq!(ProxyChecker::interval(&pcs, &proxy(u32::MAX, false), false), 60 << 32);
+}
+
+#[test]
+fn schedule() {
+    let pcs = ProxyCheckerSettings { check_interval: 600, blacklisted_interval: 86400, ..Default::default() };
+
+    assert_eq!(ProxyChecker::interval(&pcs, &proxy(5, false), true), 600);
+    assert_eq!(ProxyChecker::interval(&pcs, &proxy(20, true), false), 86400);
+
+    // a blacklisted proxy that passed is checked like a live one until it's back
+    assert_eq!(ProxyChecker::interval(&pcs, &proxy(20, true), true), 600);
+}
+
+#[test]
+fn every_target_down() {
+    let pcs = ProxyCheckerSettings { retry_interval: 60, ..Default::default() };
+
+    // nothing was counted, only the next check moves
+    for original in vec![proxy(3, false), proxy(20, true)] {
+        let mut expected = original.clone();
+        expected.next_check_at = 1000 + 60;
+
+        assert!(ProxyChecker::judge(&pcs, original, 1000, 0.0, 0.0, 0.0) == expected);
+    }
+}
+
+#[test]
+fn weighted_pass() {
+    let pcs = ProxyCheckerSettings { timeout: 10, max_fails: 5, ..Default::default() };
+
+    // passes with at least half of the counted weight
+    let judged = ProxyChecker::judge(&pcs, proxy(2, false), 1000, 2.0, 4.0, 4.0);
+    assert_eq!((judged.fails, judged.rating), (1, 4.5));
+    assert_eq!(judged.next_check_at, 1000 + pcs.check_interval);
+
+    let judged = ProxyChecker::judge(&pcs, proxy(2, false), 1000, 1.0, 4.0, 2.0);
+    assert_eq!((judged.fails, judged.rating), (3, 5.0));
+    assert_eq!(judged.next_check_at, 1000 + pcs.retry_interval * 4);
+
+    // the fail that reaches max-fails blacklists the proxy
+    let judged = ProxyChecker::judge(&pcs, proxy(4, false), 1000, 0.0, 4.0, 0.0);
+    assert!(judged.blacklisted);
+    assert_eq!(judged.blacklisted_at, Some(1000));
+}
+
+#[test]
+fn target_down() {
+    let targets = vec![target(3.0), target(1.0)];
+    let tally = |errors: [Option<&'static str>; 2]| {
+        ProxyChecker::tally(&targets, &errors.iter().map(|e| attempt(*e)).collect::<Vec<Attempt>>())
+    };
+
+    assert_eq!(tally([None, None]), (4.0, 4.0, 8.0));
+    assert_eq!(tally([None, Some(ERR_TIMEOUT)]), (3.0, 4.0, 6.0));
+    assert_eq!(tally([Some(ERR_TIMEOUT), None]), (1.0, 4.0, 2.0));
+
+    // a target that is down doesn't count, either way
+    assert_eq!(tally([Some(TARGET_DOWN), None]), (1.0, 1.0, 2.0));
+    assert_eq!(tally([Some(TARGET_DOWN), Some(ERR_TIMEOUT)]), (0.0, 1.0, 0.0));
+    assert_eq!(tally([Some(TARGET_DOWN), Some(TARGET_DOWN)]), (0.0, 0.0, 0.0));
+
+    // so the proxy passes when the only target that is up answered
+    let pcs = ProxyCheckerSettings::default();
+    let (passed, counted, secs) = tally([Some(TARGET_DOWN), None]);
+    assert_eq!(ProxyChecker::judge(&pcs, proxy(2, false), 1000, passed, counted, secs).fails, 1);
+}
@@ -1,24 +1,29 @@
 // crate
-use crate::database::ratelimited::RateLimited;
+use crate::database::ratelimited::RateLimitStore;
 use crate::database::ratelimited::RateLimitEntry;
+use crate::database::index::ProxyIndex;
+use crate::database::pool::Pool;
 use crate::helpers::{logger::{Level, Logger}, types};
+use crate::helpers::metrics::metrics;
 
 // rayon
 use rayon::prelude::*;
 
 // std
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 pub struct RatelimitUpdater {
-    ratelimited: RateLimited, // ratelimited proxies
+    ratelimited: Box<dyn RateLimitStore>, // ratelimited proxies
+    index: Arc<ProxyIndex>, // expired entries are removed through the index
     logger: Logger
 }
 
 impl RatelimitUpdater {
-    pub fn new(logger: Logger) -> Self {
-        let ratelimited = RateLimited::new()
+    pub fn new(pool: &Pool, index: Arc<ProxyIndex>, logger: Logger) -> Self {
+        let ratelimited = pool.ratelimited()
             .expect("Couldn't connect to database");
-        Self { ratelimited, logger }
+        Self { ratelimited, index, logger }
     }
 
     fn now() -> u64 {
@@ -31,8 +36,10 @@ impl RatelimitUpdater {
 
 
     pub fn update(&mut self) -> Result<(), types::AnyError> {
+        let started = Instant::now();
+        let logger = &self.logger;
         let proxies: _ = self.ratelimited.read_ratelimited(100,
-            |entries| {
+            &|entries| {
                 // get results from proxy testing
                 let rle: _ = entries.par_iter()
                     .filter(|e: _| (Self::now() as i64 - e.1.until as i64) >= 0)
@@ -40,24 +47,34 @@ impl RatelimitUpdater {
                     .collect::<Vec<RateLimitEntry>>();
 
                 let msg = format!("RateLimitManager: Found {} proxies!", rle.len());
-                self.logger.log(Level::Info, &msg);
+                logger.log_with(Level::Debug, &msg, &[("amount", &rle.len())]);
                 rle
             }
         )?;
 
         let len = proxies.len();
 
-        match self.ratelimited.remove(proxies) {
+        if self.logger.enabled(Level::Debug) {
+            for e in proxies.iter() {
+                let proxy = format!("{}:{}", e.address, e.port);
+                self.logger.log_with(Level::Debug, "RateLimitManager: Rate limit expired",
+                    &[("website", &e.website), ("proxy", &proxy)]);
+            }
+        }
+
+        match self.index.remove_ratelimited(proxies) {
             Ok(_) => {
+                metrics().ratelimits_expired.inc_by(len as u64);
                 let msg = format!("RateLimitManager: Successfully unratelimited {} proxies!", len);
-                self.logger.log(Level::Info, &msg);
+                self.logger.log_with(Level::Info, &msg, &[("amount", &len)]);
             },
             Err(why) => {
-                let msg = format!("RateLimitManager: Error {:?}", why);
-                self.logger.log(Level::Info, &msg);
+                self.logger.log_with(Level::Error, "RateLimitManager: Couldn't unratelimit the proxies",
+                    &[("amount", &len), ("error", &why)]);
             }
         };
 
+        metrics().ratelimit_updates.observe(started.elapsed().as_secs_f64());
         Ok(())
     }
 }
\ No newline at end of file
@@ -1,30 +1,84 @@
 // crate
-use crate::database::managers::{ManagerState, ManagerResult};
+use crate::database::managers::{ManagerState, ManagerResult, ManagerError, Scope};
+use crate::server::limiter::{RequestLimiter, QuotaStatus};
+use crate::database::pool::Pool;
+use crate::helpers::logger::{Logger, Level};
 
 // rocket
-use rocket::Outcome;
+use rocket::{Outcome, State};
 use rocket::http::Status;
 use rocket::request::{self, Request, FromRequest};
 
 // std
 use std::fmt;
+use std::marker::PhantomData;
+use std::ops::Deref;
 
 macro_rules! auth_error {
     ($status:ident, $err:ident) => {
         {
-            let val = (Status::$status, Self::Error::$err);
+            let val = (Status::$status, AuthorizationError::$err);
             return Outcome::Failure(val);
         }
     };
 }
 
+// marker types for `Require`, one per scope
+macro_rules! scope_markers {
+    ($($name:ident),*) => {
+        pub mod scope {
+            use super::RequiredScope;
+            use crate::database::managers::Scope;
+
+            $(
+                pub struct $name;
+
+                impl RequiredScope for $name {
+                    const SCOPE: Scope = Scope::$name;
+                }
+            )*
+        }
+    };
+}
+
 pub struct Authorization {
-    pub state: ManagerState
+    pub key_id: String,
+    pub state: ManagerState,
+    pub scopes: Vec<Scope>,
+    pub replaced: bool // see `Manager::replaced`
+}
+
+impl Authorization {
+    pub fn has(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+scope_markers!(ProxiesRead, ProxiesWrite, RatelimitsWrite, RatelimitsGlobal, ManagersAdmin, StatsRead, BlocklistWrite);
+
+// request guard that only succeeds if the token has the scope `S`, e.g. `auth: Require<scope::ProxiesRead>`
+pub struct Require<S: RequiredScope> {
+    pub auth: Authorization,
+    scope: PhantomData<S>
+}
+
+impl<S: RequiredScope> Deref for Require<S> {
+    type Target = Authorization;
+
+    fn deref(&self) -> &Authorization {
+        &self.auth
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum AuthorizationError {
     InvalidToken,
+    MissingScope,
+    RateLimited,
     SomethingWentWrong,
     Other(String)
 }
@@ -33,6 +87,8 @@ impl fmt::Display for AuthorizationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let msg = match self {
             Self::InvalidToken => "Invalid token",
+            Self::MissingScope => "Missing scope",
+            Self::RateLimited => "Too many requests",
             Self::SomethingWentWrong => "Something went wrong",
             Self::Other(s) => &s
         };
@@ -40,41 +96,121 @@ impl fmt::Display for AuthorizationError {
     }
 }
 
-impl<'a, 'r> FromRequest<'a, 'r> for Authorization {
-    type Error = AuthorizationError;
-
-    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+impl Authorization {
+    // checks the token and counts the request against its quota, the scope is up to the guard
+    fn authenticate(request: &Request) -> request::Outcome<Self, AuthorizationError> {
         let tokens: Vec<_> = request.headers().get("authorization").collect();
 
         if tokens.len() != 1 {
             return auth_error!(BadRequest, InvalidToken);
         }
 
+        let pool = match request.guard::<State<Pool>>() {
+            Outcome::Success(pool) => pool,
+            _ => return auth_error!(InternalServerError, SomethingWentWrong)
+        };
+
         // check if token is valid
-        let state = super::get_state(tokens[0]);
-        if state.is_err() {
+        let manager = super::get_manager(&pool, tokens[0]);
+        if manager.is_err() {
             return auth_error!(InternalServerError, SomethingWentWrong);
         }
-        let state = state.unwrap();
+        let manager = manager.unwrap();
 
         // check token state
-        match state {
-            ManagerResult::Ok(state) => {
-                if state == ManagerState::Disabled
-                    || state == ManagerState::Unknown
+        match manager {
+            ManagerResult::Ok(manager) => {
+                if manager.state == ManagerState::Disabled
+                    || manager.state == ManagerState::Unknown
                 {
                     return auth_error!(Unauthorized, InvalidToken);
                 }
 
+                // count the request against the token's quota
+                let limiter = match request.guard::<State<RequestLimiter>>() {
+                    Outcome::Success(limiter) => limiter,
+                    _ => return auth_error!(InternalServerError, SomethingWentWrong)
+                };
+
+                match limiter.hit(&manager.key_id, manager.quota) {
+                    Ok(Ok(quota)) => { request.local_cache(|| QuotaStatus(Some(quota))); },
+                    Ok(Err(quota)) => {
+                        request.local_cache(|| QuotaStatus(Some(quota)));
+                        return auth_error!(TooManyRequests, RateLimited);
+                    },
+                    Err(_) => return auth_error!(InternalServerError, SomethingWentWrong)
+                }
+
                 // return value
-                let strct = Authorization { state };
+                let strct = Authorization {
+                    key_id: manager.key_id,
+                    state: manager.state,
+                    scopes: manager.scopes,
+                    replaced: manager.replaced
+                };
                 Outcome::Success(strct)
             },
             ManagerResult::Err(why) => {
-                let err: _ = AuthorizationError::Other(why.into());
-                let val: _ = (Status::InternalServerError, err);
-                return Outcome::Failure(val);
+                let status = match why {
+                    ManagerError::Storage | ManagerError::UnknownScope => Status::InternalServerError,
+                    ManagerError::InvalidToken | ManagerError::Expired => Status::Unauthorized
+                };
+
+                let err: _ = AuthorizationError::Other(why.to_string());
+                return Outcome::Failure((status, err));
+            }
+        }
+    }
+
+    // called once the request passed every check. last_used_at is only bookkeeping,
+    // failing to write it mustn't fail the request
+    fn touch(&self, request: &Request) {
+        let res = match request.guard::<State<Pool>>() {
+            Outcome::Success(pool) => super::touch(&pool, &self.key_id),
+            _ => Err("The database pool isn't managed".into())
+        };
+
+        if let Err(why) = res {
+            if let Outcome::Success(logger) = request.guard::<State<Logger>>() {
+                logger.log_with(Level::Warn, "Authorization: Couldn't record the token's last use.",
+                    &[("token_id", &self.key_id), ("error", &why)]);
             }
         }
     }
-}
\ No newline at end of file
+}
+
+// any valid token, for endpoints that check what it may do themselves
+impl<'a, 'r> FromRequest<'a, 'r> for Authorization {
+    type Error = AuthorizationError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let auth = match Self::authenticate(request) {
+            Outcome::Success(auth) => auth,
+            Outcome::Failure(val) => return Outcome::Failure(val),
+            Outcome::Forward(f) => return Outcome::Forward(f)
+        };
+
+        auth.touch(request);
+        Outcome::Success(auth)
+    }
+}
+
+impl<'a, 'r, S: RequiredScope> FromRequest<'a, 'r> for Require<S> {
+    type Error = AuthorizationError;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let auth = match Authorization::authenticate(request) {
+            Outcome::Success(auth) => auth,
+            Outcome::Failure(val) => return Outcome::Failure(val),
+            Outcome::Forward(f) => return Outcome::Forward(f)
+        };
+
+        // a request without the scope isn't a use of the token
+        if !auth.has(S::SCOPE) {
+            return auth_error!(Forbidden, MissingScope);
+        }
+
+        auth.touch(request);
+        Outcome::Success(Require { auth, scope: PhantomData })
+    }
+}
@@ -0,0 +1,22 @@
+// super
+use super::Response;
+
+// rocket
+use rocket::Request;
+use rocket_contrib::json::Json;
+
+macro_rules! catcher {
+    ($name:ident, $code:expr, $msg:expr) => {
+        #[catch($code)]
+        pub fn $name(_req: &Request) -> Json<Response<&'static str>> {
+            Json(Response { code: $code, msg: $msg })
+        }
+    };
+}
+
+catcher!(bad_request, 400, "Bad request");
+catcher!(unauthorized, 401, "Unauthorized");
+catcher!(forbidden, 403, "Forbidden");
+catcher!(not_found, 404, "Not found");
+catcher!(too_many_requests, 429, "Too many requests");
+catcher!(internal_error, 500, "Internal server error");
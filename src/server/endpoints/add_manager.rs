@@ -1,6 +1,10 @@
 // crate
-use crate::server::authorization::Authorization as Auth;
-use crate::database::managers::{ManagerAuth, ManagerState};
+use crate::server::authorization::{Require, scope};
+use crate::server::{audit, summarize};
+use crate::database::managers::{ManagerAuth, Scope, TokenOptions};
+use crate::database::pool::Pool;
+use crate::helpers::logger::Logger;
+use crate::helpers::random::random_string;
 
 // serde
 use serde_derive::{Serialize, Deserialize};
@@ -8,17 +12,16 @@ use serde_derive::{Serialize, Deserialize};
 // rocket
 use rocket_contrib::json::Json;
 use rocket::response::status::BadRequest;
-
-// std
-use std::iter;
-
-// rand
-use rand::distributions::Alphanumeric;
-use rand::prelude::*;
+use rocket::State;
 
 #[derive(Deserialize)]
 pub struct AddManager {
-    state: u8
+    state: u8,
+    scopes: Option<Vec<String>>,
+    label: Option<String>,
+    owner: Option<String>,
+    expires_at: Option<u64>, // unix timestamp
+    quota: Option<u32> // requests per minute
 }
 
 #[derive(Serialize)]
@@ -26,31 +29,52 @@ pub struct AddManagerResponse {
     token: String
 }
 
-pub fn random_string(len: usize) -> String {
-    let mut rng = rand::thread_rng();
+pub fn parse_scopes(scopes: &[String]) -> Result<Vec<Scope>, String> {
+    let mut vec = Vec::new();
+
+    for s in scopes.iter() {
+        match Scope::parse(s) {
+            Some(scope) => vec.push(scope),
+            None => return Err(format!("Unknown scope: {}", s))
+        }
+    }
 
-    iter::repeat(())
-        .map(|()| rng.sample(Alphanumeric))
-        .take(len).collect()
+    Ok(vec)
 }
 
-// TODO: Implement rate limits
 #[post("/add", data = "<data>")]
-pub fn add_manager(auth: Auth, data: Json<AddManager>)
+pub fn add_manager(auth: Require<scope::ManagersAdmin>, pool: State<Pool>,
+    logger: State<Logger>, data: Json<AddManager>)
     -> Result<Json<AddManagerResponse>, BadRequest<String>>
 {
-    if auth.state != ManagerState::Admin {
-        let msg = "You must be an admin to perform this action.".into();
-        let bad_req: _ = BadRequest(Some(msg));
-        return Err(bad_req);
-    }
+    let scopes = match &data.scopes {
+        Some(scopes) => Some(parse_scopes(scopes).map_err(|why| BadRequest(Some(why)))?),
+        None => None
+    };
 
-    match ManagerAuth::new() {
-        Ok(manager) => {
+    match pool.managers() {
+        Ok(mut manager) => {
             let token = random_string(32);
-            let res: _ = manager.add_token(&token, data.state.into());
+            let options = TokenOptions {
+                label: data.label.clone(),
+                owner: data.owner.clone(),
+                expires_at: data.expires_at,
+                quota: data.quota
+            };
+
+            let res: _ = manager.add_token(&token, data.state.into(), scopes.as_deref(), &options);
+            drop(manager); // a request holds one connection at a time, see `Config::min_pool_size`
 
             if res.is_ok() {
+                let scopes = data.scopes.clone().unwrap_or_default();
+                let summary = format!("state {}, scopes [{}], label {}, owner {}, expires at {}, quota {}",
+                    data.state, summarize(&scopes),
+                    data.label.as_deref().unwrap_or("-"), data.owner.as_deref().unwrap_or("-"),
+                    data.expires_at.map(|e| e.to_string()).unwrap_or("-".into()),
+                    data.quota.map(|q| q.to_string()).unwrap_or("-".into()));
+
+                audit(&pool, &logger, &auth, "managers.add", &ManagerAuth::key_id(&token), &summary);
+
                 Ok(Json(AddManagerResponse { token }))
             } else {
                 Err(BadRequest(Some("Couldn't add token".into())))
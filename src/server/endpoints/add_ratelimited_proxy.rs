@@ -1,17 +1,23 @@
 // crate
-use crate::server::authorization::Authorization as Auth;
-use crate::database::ratelimited::{RateLimitEntry, RateLimited};
-use crate::database::managers::ManagerState;
+use crate::server::authorization::{Require, scope};
+use crate::server::{audit, summarize};
+use crate::database::ratelimited::RateLimitEntry;
+use crate::database::index::ProxyIndex;
+use crate::database::managers::Scope;
+use crate::database::pool::Pool;
+use crate::helpers::logger::{Level, Logger};
 use crate::helpers::types;
 
 // rocket
 use rocket_contrib::json::Json;
 use rocket::http::Status;
+use rocket::State;
 
 // serde
 use serde_derive::Deserialize;
 
 // std
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Deserialize)]
@@ -35,15 +41,15 @@ fn now() -> u64 {
 }
 
 #[post("/add", data = "<data>")]
-pub fn add_ratelimited(auth: Auth, data: Json<RateLimitEntryInput>)
+pub fn add_ratelimited(auth: Require<scope::RatelimitsWrite>, pool: State<Pool>, index: State<Arc<ProxyIndex>>,
+    logger: State<Logger>, data: Json<RateLimitEntryInput>)
     -> Result<Status, types::AnyError>
 {
-    if data.website == "*" && auth.state != ManagerState::Admin {
-        return Ok(Status::Unauthorized);
+    if data.website == "*" && !auth.has(Scope::RatelimitsGlobal) {
+        return Ok(Status::Forbidden);
     }
 
     let mut vec = Vec::new();
-    let mut ratelimited = RateLimited::new()?;
 
     for proxy in data.proxies.iter() {
         let parts: _ = proxy.address.split("://").collect::<Vec<&str>>();
@@ -76,6 +82,14 @@ pub fn add_ratelimited(auth: Auth, data: Json<RateLimitEntryInput>)
         return Ok(Status::BadRequest);
     }
 
-    ratelimited.add(vec)?;
+    let addresses = vec.iter()
+        .map(|e| format!("{}:{} for {} secs", e.address, e.port, e.until.saturating_sub(now())))
+        .collect::<Vec<String>>();
+
+    let amount = vec.len();
+    index.add_ratelimited(vec)?;
+    logger.log_with(Level::Info, "Rate limited proxies",
+        &[("website", &data.website), ("token_id", &auth.key_id), ("amount", &amount)]);
+    audit(&pool, &logger, &auth, "ratelimits.add", &data.website, &summarize(&addresses));
     Ok(Status::Ok)
 }
\ No newline at end of file
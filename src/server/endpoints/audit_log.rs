@@ -0,0 +1,23 @@
+// crate
+use crate::server::authorization::{Require, scope};
+use crate::database::audit::{AuditEntry, AuditFilter};
+use crate::database::pool::Pool;
+use crate::helpers::types;
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::State;
+
+const MAX_PER_PAGE: u32 = 500;
+
+#[get("/?<page>&<per_page>&<key_id>&<action>&<target>&<since>&<until>")]
+pub fn audit_log(_auth: Require<scope::ManagersAdmin>, pool: State<Pool>, page: Option<u32>, per_page: Option<u32>,
+    key_id: Option<String>, action: Option<String>, target: Option<String>,
+    since: Option<u64>, until: Option<u64>)
+    -> Result<Json<Vec<AuditEntry>>, types::AnyError>
+{
+    let filter = AuditFilter { key_id, action, target, since, until };
+    let per_page = per_page.unwrap_or(50).min(MAX_PER_PAGE);
+    let entries = pool.audit()?.query(&filter, page.unwrap_or(0), per_page)?;
+    Ok(Json(entries))
+}
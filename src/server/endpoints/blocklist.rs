@@ -0,0 +1,110 @@
+// crate
+use crate::server::authorization::{Require, scope};
+use crate::server::{audit, summarize, rejected, Rejected};
+use crate::database::blocklist::{ListEntry, ListKind, Target};
+use crate::database::managers::Scope;
+use crate::database::pool::Pool;
+use crate::helpers::logger::Logger;
+use crate::helpers::types;
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::response::status::Custom;
+use rocket::http::Status;
+use rocket::State;
+
+// serde
+use serde_derive::Deserialize;
+
+#[derive(Deserialize)]
+pub struct ListInput {
+    website: String,
+    kind: ListKind,
+    targets: Vec<String>
+}
+
+// the entries, or the targets that aren't valid
+fn into_entries(data: &ListInput) -> Result<Vec<ListEntry>, Vec<String>> {
+    let mut vec = Vec::new();
+    let mut invalid = Vec::new();
+
+    for target in data.targets.iter() {
+        let target = target.trim();
+
+        if Target::parse(target).is_none() {
+            invalid.push(target.to_string());
+            continue;
+        }
+
+        vec.push(ListEntry {
+            website: data.website.clone(),
+            kind: data.kind,
+            target: target.to_string()
+        });
+    }
+
+    if invalid.len() != 0 {
+        return Err(invalid);
+    }
+
+    Ok(vec)
+}
+
+// needs blocklist:write, and blocklist:global on top for rules of every website ("*")
+#[post("/add", data = "<data>")]
+pub fn add_rules(auth: Require<scope::BlocklistWrite>, pool: State<Pool>,
+    logger: State<Logger>, data: Json<ListInput>)
+    -> Result<Result<Status, Custom<Json<Rejected>>>, types::AnyError>
+{
+    if data.website == "*" && !auth.has(Scope::BlocklistGlobal) {
+        return Ok(Ok(Status::Forbidden));
+    }
+
+    let vec = match into_entries(&data) {
+        Ok(vec) => vec,
+        Err(invalid) => return Ok(Err(rejected("Invalid targets", invalid)))
+    };
+
+    if vec.len() == 0 {
+        return Ok(Ok(Status::BadRequest));
+    }
+
+    let targets = vec.iter().map(|e| e.target.clone()).collect::<Vec<String>>();
+    pool.blocklist()?.add(vec)?;
+    audit(&pool, &logger, &auth, "blocklist.add", &data.website,
+        &format!("{:?}: {}", data.kind, summarize(&targets)));
+    Ok(Ok(Status::Ok))
+}
+
+#[delete("/remove", data = "<data>")]
+pub fn remove_rules(auth: Require<scope::BlocklistWrite>, pool: State<Pool>,
+    logger: State<Logger>, data: Json<ListInput>)
+    -> Result<Result<Status, Custom<Json<Rejected>>>, types::AnyError>
+{
+    if data.website == "*" && !auth.has(Scope::BlocklistGlobal) {
+        return Ok(Ok(Status::Forbidden));
+    }
+
+    let vec = match into_entries(&data) {
+        Ok(vec) => vec,
+        Err(invalid) => return Ok(Err(rejected("Invalid targets", invalid)))
+    };
+
+    if vec.len() == 0 {
+        return Ok(Ok(Status::BadRequest));
+    }
+
+    let targets = vec.iter().map(|e| e.target.clone()).collect::<Vec<String>>();
+    pool.blocklist()?.remove(vec)?;
+    audit(&pool, &logger, &auth, "blocklist.remove", &data.website,
+        &format!("{:?}: {}", data.kind, summarize(&targets)));
+    Ok(Ok(Status::Ok))
+}
+
+#[get("/list?<website>")]
+pub fn list_rules(_auth: Require<scope::ProxiesRead>, pool: State<Pool>, website: Option<String>)
+    -> Result<Json<Vec<ListEntry>>, types::AnyError>
+{
+    let entries = pool.blocklist()?.list(website.as_deref())?;
+    Ok(Json(entries))
+}
@@ -1,7 +1,10 @@
 // crate
-use crate::server::authorization::Authorization as Auth;
-use crate::database::managers::ManagerState;
-use crate::database::proxies::{Proxy, Proxies};
+use crate::server::authorization::{Require, scope};
+use crate::server::{audit, summarize, rejected, Rejected};
+use crate::database::index::ProxyIndex;
+use crate::database::proxies::Proxy;
+use crate::database::pool::Pool;
+use crate::helpers::logger::Logger;
 use crate::helpers::types;
 
 // serde
@@ -9,51 +12,40 @@ use serde_derive::Deserialize;
 use rocket_contrib::json::Json;
 
 // rocket
+use rocket::response::status::Custom;
 use rocket::http::Status;
+use rocket::State;
+
+// std
+use std::sync::Arc;
 
 #[derive(Deserialize)]
 pub struct BulkInsertProxies {
     proxies: Vec<String>
 }
 
-// TODO: Implement rate limits
 #[post("/add", data = "<data>")]
-pub fn bulk_insert_proxies(auth: Auth, data: Json<BulkInsertProxies>)
-    -> Result<Status, types::AnyError>
+pub fn bulk_insert_proxies(auth: Require<scope::ProxiesWrite>, pool: State<Pool>, index: State<Arc<ProxyIndex>>,
+    logger: State<Logger>, data: Json<BulkInsertProxies>)
+    -> Result<Result<Status, Custom<Json<Rejected>>>, types::AnyError>
 {
-    if auth.state != ManagerState::Admin {
-        return Ok(Status::Unauthorized);
-    }
-
-    // parse proxies
-    let proxies: Vec<Proxy> = {
-        let mut vec = Vec::new();
-
-        for proxy in data.proxies.clone().into_iter() {
-            let parts = proxy.split("://").collect::<Vec<&str>>();
-
-            if parts.len() != 2 {
-                continue;
-            }
-
-            let sub_parts = parts[1].split(":").collect::<Vec<&str>>();
-
-            if sub_parts.len() != 2 {
-                continue;
-            }
-
-            vec.push(Proxy {
-                schema: parts[0].into(),
-                address: sub_parts[0].into(),
-                port: sub_parts[1].replace("/", "").parse::<u16>()?,
-                rating: 0.0f64, fails: 0,
-                blacklisted: false
-            })
+    // parse proxies, nothing is inserted if any of them is invalid
+    let mut proxies = Vec::with_capacity(data.proxies.len());
+    let mut invalid = Vec::new();
+
+    for text in data.proxies.iter() {
+        match Proxy::parse(text) {
+            Some(proxy) => proxies.push(proxy),
+            None => invalid.push(text.clone())
         }
+    }
 
-        vec
-    };
+    if invalid.len() > 0 {
+        return Ok(Err(rejected("Invalid proxies", invalid)));
+    }
 
-    Proxies::new()?.insert_proxies(proxies)?;
-    Ok(Status::Ok)
+    let summary = format!("{} proxies: {}", proxies.len(), summarize(&data.proxies));
+    index.insert_proxies(proxies)?;
+    audit(&pool, &logger, &auth, "proxies.add", "proxies", &summary);
+    Ok(Ok(Status::Ok))
 }
\ No newline at end of file
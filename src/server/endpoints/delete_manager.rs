@@ -0,0 +1,25 @@
+// crate
+use crate::server::authorization::{Require, scope};
+use crate::server::audit;
+use crate::database::managers::ManagerAuth;
+use crate::database::pool::Pool;
+use crate::helpers::logger::Logger;
+use crate::helpers::types;
+
+// rocket
+use rocket::http::Status;
+use rocket::State;
+
+#[delete("/<id>")]
+pub fn delete_manager(auth: Require<scope::ManagersAdmin>, pool: State<Pool>, logger: State<Logger>, id: String)
+    -> Result<Status, types::AnyError>
+{
+    let key_id = ManagerAuth::key_id(&id);
+
+    if !pool.managers()?.delete(&key_id)? {
+        return Ok(Status::NotFound);
+    }
+
+    audit(&pool, &logger, &auth, "managers.delete", &key_id, "deleted");
+    Ok(Status::Ok)
+}
@@ -1,7 +1,10 @@
 // crate
-use crate::server::authorization::Authorization as Auth;
-use crate::database::ratelimited::RateLimited;
-use crate::database::proxies::{Proxy, Proxies};
+use crate::server::authorization::{Require, scope};
+use crate::database::index::ProxyIndex;
+use crate::database::proxies::Proxy;
+use crate::database::pool::Pool;
+use crate::helpers::logger::{Level, Logger};
+use crate::helpers::metrics::metrics;
 use crate::helpers::types;
 
 // serde
@@ -9,6 +12,10 @@ use serde_derive::Deserialize;
 
 // rocket
 use rocket_contrib::json::Json;
+use rocket::State;
+
+// std
+use std::sync::Arc;
 
 #[derive(Deserialize)]
 pub struct GetProxy {
@@ -18,42 +25,17 @@ pub struct GetProxy {
 }
 
 
-// TODO: Implement rate limits
 #[get("/get", data = "<data>")]
-pub fn get_proxy(auth: Auth, data: Json<GetProxy>)
+pub fn get_proxy(auth: Require<scope::ProxiesRead>, pool: State<Pool>, index: State<Arc<ProxyIndex>>,
+    logger: State<Logger>, data: Json<GetProxy>)
     -> Result<Json<Vec<Proxy>>, types::AnyError>
 {
-    let proxy = Proxies::new()?;
-    let mut rl = RateLimited::new()?;
-    let mut idx = 0;
-
-    loop {
-        // get proxies
-        let proxies = if data.min_rating.is_some() {
-            proxy.over(data.min_rating.unwrap(), data.amount)
-        } else {
-            proxy.top_rated(data.amount)
-        };
-
-        // flatten into Vec<Proxy>
-        let mut proxies = proxies?.iter()
-            .map(|entry| entry.1.clone())
-            .collect::<Vec<Proxy>>();
-
-        // get rate limited proxies and bare proxies
-        let ratelimited = rl.get_ratelimited(&data.website, proxies.clone())?;
-
-        // remove rate limited proxies
-        for (idx, proxy) in proxies.clone().iter().enumerate() {
-            if ratelimited.contains(proxy) {
-                proxies.remove(idx);
-            }
-        }
-
-        if (proxies.len() >= data.amount as usize) || idx == 5 {
-            return Ok(Json(proxies));
-        }
-
-        idx += 1;
-    }
-}
\ No newline at end of file
+    // proxies that are blocked on (or not allowed for) the website are skipped
+    let rules = pool.blocklist()?.rules(&data.website)?;
+    let proxies = index.select(&data.website, data.amount, data.min_rating, |p| rules.permits(p))?;
+
+    metrics().handouts.with_label_values(&[&auth.key_id]).inc_by(proxies.len() as u64);
+    logger.log_with(Level::Debug, "Handed out proxies",
+        &[("website", &data.website), ("token_id", &auth.key_id), ("amount", &proxies.len())]);
+    Ok(Json(proxies))
+}
@@ -0,0 +1,92 @@
+// crate
+use crate::database::index::ProxyIndex;
+use crate::database::pool::Pool;
+use crate::health::Health;
+use crate::helpers::logger::{Level, Logger};
+use crate::helpers::types;
+
+// serde
+use serde_derive::Serialize;
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::response::status::Custom;
+use rocket::http::Status;
+use rocket::State;
+
+// std
+use std::sync::Arc;
+
+#[derive(Serialize)]
+pub struct HealthResponse {
+    healthy: bool,
+    database: &'static str, // the backend of the proxies, "sqlite" or "postgres"
+    error: Option<&'static str> // the details are logged, not shown to anyone who asks
+}
+
+#[derive(Serialize)]
+pub struct ReadyResponse {
+    ready: bool,
+    live_proxies: usize,
+    min_proxies: usize,
+    last_check: Option<u64>, // unix secs of the last finished proxy checker pass
+    last_ratelimit_update: Option<u64>,
+    reasons: Vec<String> // why it isn't ready
+}
+
+const DATABASE_DOWN: &str = "The database can't be reached";
+
+fn status(ok: bool) -> Status {
+    if ok { Status::Ok } else { Status::ServiceUnavailable }
+}
+
+fn ping(pool: &Pool, logger: &Logger) -> bool {
+    match pool.ping() {
+        Ok(_) => true,
+        Err(why) => {
+            logger.log_with(Level::Error, "Health: The database can't be reached", &[("error", &why)]);
+            false
+        }
+    }
+}
+
+// unauthenticated for the orchestrator: the process is up and the database answers
+#[get("/health")]
+pub fn health(pool: State<Pool>, logger: State<Logger>) -> Custom<Json<HealthResponse>> {
+    let healthy = ping(&pool, &logger);
+    let error = if healthy { None } else { Some(DATABASE_DOWN) };
+
+    Custom(status(healthy), Json(HealthResponse { healthy, database: pool.backend(), error }))
+}
+
+// unauthenticated too: healthy, enough live proxies and a proxy checker that keeps finishing passes
+#[get("/ready")]
+pub fn ready(pool: State<Pool>, index: State<Arc<ProxyIndex>>, health: State<Arc<Health>>, logger: State<Logger>)
+    -> Result<Custom<Json<ReadyResponse>>, types::AnyError>
+{
+    let live_proxies = index.live()?;
+    let mut reasons = Vec::new();
+
+    if !ping(&pool, &logger) {
+        reasons.push(DATABASE_DOWN.into());
+    }
+
+    if live_proxies < health.min_proxies {
+        reasons.push(format!("{} live proxies, at least {} are required", live_proxies, health.min_proxies));
+    }
+
+    if health.checker_stale() {
+        reasons.push(format!("The proxy checker hasn't finished a pass in {} secs", health.max_staleness));
+    }
+
+    let ready = reasons.len() == 0;
+
+    Ok(Custom(status(ready), Json(ReadyResponse {
+        ready,
+        live_proxies,
+        min_proxies: health.min_proxies,
+        last_check: health.last_check(),
+        last_ratelimit_update: health.last_ratelimit_update(),
+        reasons
+    })))
+}
@@ -0,0 +1,17 @@
+// crate
+use crate::server::authorization::{Require, scope};
+use crate::database::managers::ManagerInfo;
+use crate::database::pool::Pool;
+use crate::helpers::types;
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::State;
+
+#[get("/")]
+pub fn list_managers(_auth: Require<scope::ManagersAdmin>, pool: State<Pool>)
+    -> Result<Json<Vec<ManagerInfo>>, types::AnyError>
+{
+    let managers = pool.managers()?.list()?;
+    Ok(Json(managers))
+}
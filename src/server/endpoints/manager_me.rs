@@ -0,0 +1,32 @@
+// crate
+use crate::server::authorization::Authorization as Auth;
+use crate::server::limiter::{RequestLimiter, Quota};
+use crate::database::managers::ManagerInfo;
+use crate::database::pool::Pool;
+use crate::helpers::types;
+
+// serde
+use serde_derive::Serialize;
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::State;
+
+#[derive(Serialize)]
+pub struct MeResponse {
+    manager: ManagerInfo,
+    usage: Quota
+}
+
+#[get("/me")]
+pub fn me(auth: Auth, pool: State<Pool>, limiter: State<RequestLimiter>)
+    -> Result<Option<Json<MeResponse>>, types::AnyError>
+{
+    let manager = match pool.managers()?.get_info(&auth.key_id)? {
+        Some(manager) => manager,
+        None => return Ok(None)
+    };
+
+    let usage = limiter.peek(&auth.key_id, manager.quota)?;
+    Ok(Some(Json(MeResponse { manager, usage })))
+}
@@ -0,0 +1,55 @@
+// crate
+use crate::server::authorization::{Require, scope};
+use crate::helpers::metrics::metrics as registry;
+use crate::database::pool::Pool;
+use crate::database::proxies;
+use crate::helpers::types;
+
+// rocket
+use rocket::response::content::Plain;
+use rocket::State;
+
+// std
+use std::collections::HashMap;
+
+// needs a token because of the manager key ids, prometheus sends it as the authorization
+// header. the pool gauges are read from the database
+#[get("/metrics")]
+pub fn metrics(_auth: Require<scope::StatsRead>, pool: State<Pool>) -> Result<Plain<String>, types::AnyError> {
+    let metrics = registry();
+    let now = proxies::now();
+
+    let mut live = 0;
+    let mut blacklisted = 0;
+    let mut schemas = HashMap::new();
+
+    for (schema, is_blacklisted, count) in pool.proxies()?.census()? {
+        if is_blacklisted {
+            blacklisted += count;
+        } else {
+            live += count;
+            *schemas.entry(schema).or_insert(0) += count;
+        }
+    }
+
+    let mut ratelimited = pool.ratelimited()?;
+    let limited = ratelimited.limited_proxies(now)?;
+
+    for (state, count) in [("live", live), ("blacklisted", blacklisted), ("ratelimited", limited)].iter() {
+        metrics.proxies.with_label_values(&[state]).set(*count as i64);
+    }
+
+    // schemas and websites that are gone shouldn't linger
+    metrics.proxies_by_schema.reset();
+    metrics.ratelimits.reset();
+
+    for (schema, count) in schemas {
+        metrics.proxies_by_schema.with_label_values(&[&schema]).set(count as i64);
+    }
+
+    for (website, count) in ratelimited.active(now)? {
+        metrics.ratelimits.with_label_values(&[&website]).set(count as i64);
+    }
+
+    Ok(Plain(metrics.render()?))
+}
@@ -4,4 +4,14 @@ pub mod bulk_insert_proxies;
 pub mod add_ratelimited_proxy;
 pub mod add_manager;
 pub mod modify_manager;
-pub mod get_proxy;
\ No newline at end of file
+pub mod rotate_manager;
+pub mod list_managers;
+pub mod delete_manager;
+pub mod manager_me;
+pub mod get_proxy;
+pub mod proxy_history;
+pub mod blocklist;
+pub mod audit_log;
+pub mod reload_config;
+pub mod metrics;
+pub mod health;
\ No newline at end of file
@@ -1,32 +1,90 @@
 // crate
-use crate::server::authorization::Authorization as Auth;
-use crate::database::managers::{ManagerAuth, ManagerState};
+use crate::server::authorization::{Require, scope};
+use crate::server::{audit, summarize};
+use crate::server::endpoints::add_manager::parse_scopes;
+use crate::database::managers::ManagerAuth;
+use crate::database::pool::Pool;
+use crate::helpers::logger::Logger;
 use crate::helpers::types;
 
 // serde
+use serde::{Deserialize as _, Deserializer};
+use serde::de::Error as _;
 use serde_derive::Deserialize;
 
 // rocket
 use rocket_contrib::json::Json;
 use rocket::http::Status;
+use rocket::State;
 
 #[derive(Deserialize)]
 pub struct ModifyManager {
+    // the manager's key id, or its whole token
+    #[serde(alias = "id")]
     token: String,
-    state: u8
+    state: u8,
+    scopes: Option<Vec<String>>,
+    // requests per minute, 0 for unlimited, null or "reset" goes back to the configured default
+    #[serde(default, deserialize_with = "quota_update")]
+    quota: Option<Option<u32>>
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QuotaValue {
+    Limit(u32),
+    Word(String)
+}
+
+// a missing quota is left alone (`None`), an explicit null or "reset" clears it (`Some(None)`)
+pub(crate) fn quota_update<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<u32>>, D::Error> {
+    match Option::<QuotaValue>::deserialize(deserializer)? {
+        Some(QuotaValue::Limit(limit)) => Ok(Some(Some(limit))),
+        Some(QuotaValue::Word(word)) if word == "reset" => Ok(Some(None)),
+        Some(QuotaValue::Word(word)) => Err(D::Error::custom(format!("invalid quota {:?}, expected a number, null or \"reset\"", word))),
+        None => Ok(Some(None))
+    }
 }
 
-// TODO: Implement rate limits
 #[patch("/modify", data = "<data>")]
-pub fn modify_manager(auth: Auth, data: Json<ModifyManager>)
+pub fn modify_manager(auth: Require<scope::ManagersAdmin>, pool: State<Pool>,
+    logger: State<Logger>, data: Json<ModifyManager>)
     -> Result<Status, types::AnyError>
 {
-    if auth.state != ManagerState::Admin {
-        return Ok(Status::Unauthorized);
+    let scopes = match &data.scopes {
+        Some(scopes) => match parse_scopes(scopes) {
+            Ok(scopes) => Some(scopes),
+            Err(_) => return Ok(Status::BadRequest)
+        },
+        None => None
+    };
+
+    let mut manager = pool.managers()?;
+    let key_id = ManagerAuth::key_id(&data.token);
+
+    // the other updates find the manager if this one did
+    if !manager.update_state(&key_id, data.state.into())? {
+        return Ok(Status::NotFound);
+    }
+
+    if let Some(scopes) = scopes {
+        manager.update_scopes(&key_id, &scopes)?;
     }
 
-    let manager = ManagerAuth::new()?;
-    manager.update_state(&data.token, data.state.into())?;
+    if let Some(quota) = data.quota {
+        manager.update_quota(&key_id, quota)?;
+    }
+
+    drop(manager); // a request holds one connection at a time, see `Config::min_pool_size`
+
+    let summary = format!("state {}, scopes {}, quota {}", data.state,
+        data.scopes.as_ref().map(|s| format!("[{}]", summarize(s))).unwrap_or("unchanged".into()),
+        match data.quota {
+            Some(Some(quota)) => quota.to_string(),
+            Some(None) => "reset".into(),
+            None => "unchanged".into()
+        });
+    audit(&pool, &logger, &auth, "managers.modify", &key_id, &summary);
 
     Ok(Status::Ok)
 }
\ No newline at end of file
@@ -0,0 +1,45 @@
+// crate
+use crate::server::authorization::{Require, scope};
+use crate::database::history::{ProbeRecord, Rollup, HOUR, DAY};
+use crate::database::proxies::Proxy;
+use crate::database::pool::Pool;
+use crate::helpers::types;
+
+// serde
+use serde_derive::Serialize;
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::State;
+
+const MAX_LIMIT: u32 = 1000;
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    proxy: Proxy,
+    probes: Vec<ProbeRecord>,
+    hourly: Vec<Rollup>,
+    daily: Vec<Rollup>
+}
+
+// the probes and rollups from `since` on, each list newest first and at most `limit` long
+#[get("/<id>/history?<since>&<limit>")]
+pub fn proxy_history(_auth: Require<scope::StatsRead>, pool: State<Pool>, id: u32, since: Option<u64>, limit: Option<u32>)
+    -> Result<Option<Json<HistoryResponse>>, types::AnyError>
+{
+    let proxy = match pool.proxies()?.by_id(id)? {
+        Some(proxy) => proxy,
+        None => return Ok(None)
+    };
+
+    let since = since.unwrap_or(0);
+    let limit = limit.unwrap_or(100).min(MAX_LIMIT);
+    let mut history = pool.history()?;
+
+    Ok(Some(Json(HistoryResponse {
+        proxy,
+        probes: history.probes(id, since, limit)?,
+        hourly: history.rollups(id, HOUR, since, limit)?,
+        daily: history.rollups(id, DAY, since, limit)?
+    })))
+}
@@ -0,0 +1,31 @@
+// crate
+use crate::server::authorization::{Require, scope};
+use crate::server::{audit, Response};
+use crate::config_reloader::ConfigReloader;
+use crate::database::pool::Pool;
+use crate::helpers::logger::Logger;
+use crate::helpers::types;
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::response::status::Custom;
+use rocket::http::Status;
+use rocket::State;
+
+// std
+use std::sync::Arc;
+
+// re-reads the config file, the old settings stay active if the new config is invalid
+#[post("/reload")]
+pub fn reload_config(auth: Require<scope::ManagersAdmin>, pool: State<Pool>,
+    logger: State<Logger>, reloader: State<Arc<ConfigReloader>>)
+    -> Result<Custom<Json<Response<String>>>, types::AnyError>
+{
+    let (status, msg) = match reloader.reload() {
+        Ok(_) => (Status::Ok, "Reloaded the proxy checker settings".to_string()),
+        Err(why) => (Status::BadRequest, why.to_string())
+    };
+
+    audit(&pool, &logger, &auth, "config.reload", "proxy-checker-settings", &msg);
+    Ok(Custom(status, Json(Response { code: status.code, msg })))
+}
@@ -0,0 +1,74 @@
+// crate
+use crate::server::authorization::Authorization as Auth;
+use crate::server::audit;
+use crate::database::managers::{ManagerAuth, Scope};
+use crate::database::pool::Pool;
+use crate::helpers::logger::Logger;
+use crate::helpers::types;
+
+// serde
+use serde_derive::{Serialize, Deserialize};
+
+// rocket
+use rocket_contrib::json::Json;
+use rocket::response::status::Custom;
+use rocket::http::Status;
+use rocket::State;
+
+// one day
+const DEFAULT_GRACE_PERIOD: u64 = 86400;
+
+// 30 days, the old token shouldn't outlive a rotation by much
+const MAX_GRACE_PERIOD: u64 = 30 * 86400;
+
+#[derive(Deserialize)]
+pub struct RotateManager {
+    id: Option<String>, // key id of another manager, admins only
+    grace_period: Option<u64> // secs the old token stays valid
+}
+
+#[derive(Serialize)]
+pub struct RotateManagerResponse {
+    token: String,
+    grace_period: u64
+}
+
+#[post("/rotate", data = "<data>")]
+pub fn rotate_manager(auth: Auth, pool: State<Pool>, logger: State<Logger>, data: Json<RotateManager>)
+    -> Result<Result<Json<RotateManagerResponse>, Custom<String>>, types::AnyError>
+{
+    // a leaked old token mustn't be able to lock out the new one
+    if auth.replaced {
+        let msg = "This token was replaced by a rotation, rotate with the new one.".into();
+        return Ok(Err(Custom(Status::Forbidden, msg)));
+    }
+
+    let key_id = match &data.id {
+        Some(id) if ManagerAuth::key_id(id) != auth.key_id => {
+            if !auth.has(Scope::ManagersAdmin) {
+                let msg = "Only admins can rotate other managers' tokens.".into();
+                return Ok(Err(Custom(Status::Forbidden, msg)));
+            }
+
+            ManagerAuth::key_id(id)
+        },
+        _ => auth.key_id.clone()
+    };
+
+    let grace_period = data.grace_period.unwrap_or(DEFAULT_GRACE_PERIOD);
+
+    if grace_period > MAX_GRACE_PERIOD {
+        let msg = format!("The grace period can be at most {} secs.", MAX_GRACE_PERIOD);
+        return Ok(Err(Custom(Status::BadRequest, msg)));
+    }
+
+    let rotated = pool.managers()?.rotate(&key_id, grace_period);
+
+    match rotated {
+        Ok(token) => {
+            audit(&pool, &logger, &auth, "managers.rotate", &key_id, &format!("grace period {} secs", grace_period));
+            Ok(Ok(Json(RotateManagerResponse { token, grace_period })))
+        },
+        Err(why) => Ok(Err(Custom(Status::NotFound, format!("{}", why))))
+    }
+}
@@ -0,0 +1,121 @@
+// crate
+use crate::helpers::types;
+
+// serde
+use serde_derive::Serialize;
+
+// rocket
+use rocket::{Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Status};
+
+// std
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Serialize)]
+pub struct Quota {
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset: u64 // secs until the window resets
+}
+
+// cached per request by the authorization guard, read by `QuotaHeaders`
+pub struct QuotaStatus(pub Option<Quota>);
+
+struct Window {
+    started: Instant,
+    count: u32
+}
+
+// fixed window request limiter keyed by the managers' key ids
+pub struct RequestLimiter {
+    default_quota: u32,
+    window: Duration,
+    windows: Mutex<HashMap<String, Window>>
+}
+
+impl RequestLimiter {
+    // a quota of 0 means unlimited
+    pub fn new(default_quota: u32) -> Self {
+        Self::with_window(default_quota, WINDOW)
+    }
+
+    pub(crate) fn with_window(default_quota: u32, window: Duration) -> Self {
+        Self { default_quota, window, windows: Mutex::new(HashMap::new()) }
+    }
+
+    fn quota(&self, window: &Window, limit: u32) -> Quota {
+        let elapsed = window.started.elapsed();
+        let reset = self.window.checked_sub(elapsed).unwrap_or_default().as_secs();
+        let remaining = limit.saturating_sub(window.count);
+        Quota { limit, remaining, reset }
+    }
+
+    // counts a request, `Err` if the token has used up its quota
+    pub fn hit(&self, key_id: &str, quota: Option<u32>) -> Result<Result<Quota, Quota>, types::AnyError> {
+        let limit = quota.unwrap_or(self.default_quota);
+        let mut windows = self.windows.lock().map_err(|_| "Request limiter poisoned")?;
+
+        let window = windows.entry(key_id.to_string())
+            .or_insert(Window { started: Instant::now(), count: 0 });
+
+        if window.started.elapsed() >= self.window {
+            window.started = Instant::now();
+            window.count = 0;
+        }
+
+        if limit == 0 {
+            return Ok(Ok(Quota { limit, remaining: u32::MAX, reset: 0 }));
+        }
+
+        if window.count >= limit {
+            return Ok(Err(self.quota(window, limit)));
+        }
+
+        window.count += 1;
+        Ok(Ok(self.quota(window, limit)))
+    }
+
+    // the current quota of a token without counting a request
+    pub fn peek(&self, key_id: &str, quota: Option<u32>) -> Result<Quota, types::AnyError> {
+        let limit = quota.unwrap_or(self.default_quota);
+        let windows = self.windows.lock().map_err(|_| "Request limiter poisoned")?;
+
+        match windows.get(key_id) {
+            Some(window) if window.started.elapsed() < self.window => Ok(self.quota(window, limit)),
+            _ => Ok(Quota { limit, remaining: limit, reset: 0 })
+        }
+    }
+}
+
+// adds the quota headers to every response of an authorized request
+pub struct QuotaHeaders;
+
+impl Fairing for QuotaHeaders {
+    fn info(&self) -> Info {
+        Info { name: "Quota headers", kind: Kind::Response }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let quota = match request.local_cache(|| QuotaStatus(None)).0 {
+            Some(quota) => quota,
+            None => return
+        };
+
+        if quota.limit == 0 {
+            return;
+        }
+
+        response.set_header(Header::new("X-RateLimit-Limit", quota.limit.to_string()));
+        response.set_header(Header::new("X-RateLimit-Remaining", quota.remaining.to_string()));
+        response.set_header(Header::new("X-RateLimit-Reset", quota.reset.to_string()));
+
+        if response.status() == Status::TooManyRequests {
+            response.set_header(Header::new("Retry-After", quota.reset.max(1).to_string()));
+        }
+    }
+}
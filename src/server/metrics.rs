@@ -0,0 +1,37 @@
+// crate
+use crate::helpers::metrics::metrics;
+
+// rocket
+use rocket::{Data, Request, Response};
+use rocket::fairing::{Fairing, Info, Kind};
+
+// std
+use std::time::Instant;
+
+// cached per request by `RequestMetrics`
+struct Started(Instant);
+
+// counts every request and times it by method, route and status. the route is the
+// mounted pattern, e.g. /proxies/<id>/history, so ids don't become labels.
+pub struct RequestMetrics;
+
+impl Fairing for RequestMetrics {
+    fn info(&self) -> Info {
+        Info { name: "Request metrics", kind: Kind::Request | Kind::Response }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        request.local_cache(|| Started(Instant::now()));
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let started = request.local_cache(|| Started(Instant::now())).0;
+        let route = request.route().map_or("unmatched".to_string(), |r| r.uri.path().to_string());
+        let method = request.method().as_str();
+        let status = response.status().code.to_string();
+        let labels = [method, route.as_str(), status.as_str()];
+
+        metrics().requests.with_label_values(&labels).inc();
+        metrics().request_latency.with_label_values(&labels).observe(started.elapsed().as_secs_f64());
+    }
+}
@@ -1,22 +1,57 @@
 pub mod endpoints;
 pub mod catchers;
+pub mod limiter;
+pub mod metrics;
 
 #[allow(unreachable_code)]
 pub mod authorization;
 
+#[cfg(test)]
+mod tests;
+
 // serde
 use serde_derive::Serialize;
 
 // crate
-use crate::database::managers::{ManagerAuth, ManagerResult, ManagerState};
+use crate::database::managers::{ManagerResult, Manager};
+use crate::database::index::ProxyIndex;
+use crate::database::pool::Pool;
 use crate::helpers::types;
+use crate::helpers::config::Config;
+use crate::helpers::logger::{Logger, Level};
+use crate::config_reloader::ConfigReloader;
+use crate::health::Health;
+
+// server
+use limiter::{RequestLimiter, QuotaHeaders};
+use metrics::RequestMetrics;
+use authorization::Authorization;
+
+// rocket
+use rocket::config::{Config as RocketConfig, Environment, Limits};
+use rocket::response::status::Custom;
+use rocket::http::Status;
+use rocket_contrib::json::Json;
 
 // endpoints
 use endpoints::add_ratelimited_proxy as arp;
 use endpoints::bulk_insert_proxies as bip;
 use endpoints::get_proxy as gp;
+use endpoints::proxy_history as ph;
 use endpoints::add_manager as am;
 use endpoints::modify_manager as mm;
+use endpoints::rotate_manager as rm;
+use endpoints::list_managers as lm;
+use endpoints::delete_manager as dm;
+use endpoints::manager_me as me;
+use endpoints::blocklist as bl;
+use endpoints::audit_log as al;
+use endpoints::reload_config as rc;
+use endpoints::metrics as mt;
+use endpoints::health as hl;
+
+// std
+use std::sync::Arc;
 
 #[derive(Serialize)]
 pub struct Response<T> {
@@ -24,23 +59,114 @@ pub struct Response<T> {
     msg: T
 }
 
-pub fn get_state(token: &str)
-    -> Result<ManagerResult<ManagerState>, types::AnyError>
+// a 400 listing the parts of a request that couldn't be parsed, nothing of it is applied
+#[derive(Serialize, Debug)]
+pub struct Rejected {
+    code: u16,
+    msg: &'static str,
+    rejected: Vec<String>
+}
+
+pub fn rejected(msg: &'static str, rejected: Vec<String>) -> Custom<Json<Rejected>> {
+    Custom(Status::BadRequest, Json(Rejected { code: 400, msg, rejected }))
+}
+
+pub fn get_manager(pool: &Pool, token: &str)
+    -> Result<ManagerResult<Manager>, types::AnyError>
 {
-    let man = ManagerAuth::new()?;
-    Ok(man.get_state(token))
+    Ok(pool.managers()?.get_manager(token))
+}
+
+// records when a token was last used, only after it passed every check
+pub fn touch(pool: &Pool, key_id: &str) -> Result<(), types::AnyError> {
+    pool.managers()?.touch(key_id)
+}
+
+// records a write operation of `auth` in the audit log. the operation has already
+// been applied, so a failure is logged instead of failing the request
+pub fn audit(pool: &Pool, logger: &Logger, auth: &Authorization, action: &str, target: &str, summary: &str) {
+    let res = pool.audit().and_then(|mut log| log.record(&auth.key_id, action, target, summary));
+
+    if let Err(why) = res {
+        logger.log_with(Level::Error, "Audit: Couldn't record a write operation.", &[
+            ("token_id", &auth.key_id), ("action", &action), ("target", &target),
+            ("summary", &summary), ("error", &why)
+        ]);
+    }
+}
+
+// "a, b, c and 7 more"
+pub fn summarize<T: AsRef<str>>(items: &[T]) -> String {
+    let shown = items.iter()
+        .take(10)
+        .map(|i| i.as_ref())
+        .collect::<Vec<&str>>()
+        .join(", ");
+
+    match items.len() {
+        0..=10 => shown,
+        n => format!("{} and {} more", shown, n - 10)
+    }
+}
+
+fn rocket_config(config: &Config) -> Result<RocketConfig, types::AnyError> {
+    let server = &config.server;
+    let limits = Limits::new()
+        .limit("json", server.json_limit)
+        .limit("forms", server.forms_limit);
+
+    let mut builder = RocketConfig::build(Environment::active()?)
+        .address(server.address.clone())
+        .port(server.port)
+        .limits(limits);
+
+    if let Some(workers) = server.workers {
+        builder = builder.workers(workers);
+    }
+
+    if let (Some(certs), Some(key)) = (&server.tls_certs, &server.tls_key) {
+        builder = builder.tls(certs.clone(), key.clone());
+    }
+
+    Ok(builder.finalize()?)
 }
 
-pub fn start() {
-    let proxy_routes = routes![bip::bulk_insert_proxies, gp::get_proxy];
+pub fn start(config: &Config, pool: Pool, index: Arc<ProxyIndex>, reloader: Arc<ConfigReloader>, health: Arc<Health>,
+    logger: Logger)
+    -> Result<(), types::AnyError>
+{
+    let proxy_routes = routes![bip::bulk_insert_proxies, gp::get_proxy, ph::proxy_history];
     let rl_routes = routes![arp::add_ratelimited];
-    let manager_routes = routes![am::add_manager, mm::modify_manager];
+    let manager_routes = routes![
+        am::add_manager, mm::modify_manager, rm::rotate_manager,
+        lm::list_managers, dm::delete_manager, me::me
+    ];
+    let blocklist_routes = routes![bl::add_rules, bl::remove_rules, bl::list_rules];
+    let audit_routes = routes![al::audit_log];
+    let config_routes = routes![rc::reload_config];
+    let root_routes = routes![mt::metrics, hl::health, hl::ready];
 
     // mount and ignite
-    let endpoints = rocket::ignite()
+    let endpoints = rocket::custom(rocket_config(config)?)
         .mount("/proxies", proxy_routes)
         .mount("/ratelimited", rl_routes)
-        .mount("/managers", manager_routes);
+        .mount("/managers", manager_routes)
+        .mount("/blocklist", blocklist_routes)
+        .mount("/audit", audit_routes)
+        .mount("/config", config_routes)
+        .mount("/", root_routes)
+        .register(catchers![
+            catchers::bad_request, catchers::unauthorized, catchers::forbidden,
+            catchers::not_found, catchers::too_many_requests, catchers::internal_error
+        ])
+        .manage(RequestLimiter::new(config.general.requests_per_minute))
+        .manage(pool)
+        .manage(index)
+        .manage(reloader)
+        .manage(health)
+        .manage(logger)
+        .attach(QuotaHeaders)
+        .attach(RequestMetrics);
 
-    endpoints.launch();
+    Err(endpoints.launch().into())
 }
@@ -0,0 +1,110 @@
+// the request limiter and the headers it adds, without a database
+
+// crate
+use crate::server::limiter::{RequestLimiter, QuotaHeaders, QuotaStatus};
+
+// serde
+use serde_derive::Deserialize;
+
+// rocket
+use rocket::{Outcome, State};
+use rocket::http::Status;
+use rocket::local::Client;
+use rocket::request::{self, Request, FromRequest};
+
+// std
+use std::thread;
+use std::time::Duration;
+
+// counts every request against one token, like the authorization guard
+struct Limited;
+
+impl<'a, 'r> FromRequest<'a, 'r> for Limited {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        let limiter = request.guard::<State<RequestLimiter>>().unwrap();
+
+        match limiter.hit("KEYID123", None).unwrap() {
+            Ok(quota) => {
+                request.local_cache(|| QuotaStatus(Some(quota)));
+                Outcome::Success(Limited)
+            },
+            Err(quota) => {
+                request.local_cache(|| QuotaStatus(Some(quota)));
+                Outcome::Failure((Status::TooManyRequests, ()))
+            }
+        }
+    }
+}
+
+#[get("/")]
+fn limited(_limited: Limited) -> &'static str {
+    "ok"
+}
+
+#[test]
+fn fixed_window() {
+    let limiter = RequestLimiter::with_window(2, Duration::from_millis(200));
+
+    let first = limiter.hit("KEYID123", None).unwrap().ok().unwrap();
+    assert_eq!((first.limit, first.remaining), (2, 1));
+    assert_eq!(limiter.hit("KEYID123", None).unwrap().ok().unwrap().remaining, 0);
+    assert!(limiter.hit("KEYID123", None).unwrap().is_err());
+
+    // other tokens have their own window, a per token quota replaces the default
+    assert!(limiter.hit("OTHERKEY", None).unwrap().is_ok());
+    assert_eq!(limiter.hit("QUOTAKEY", Some(5)).unwrap().ok().unwrap().remaining, 4);
+    assert_eq!(limiter.peek("KEYID123", None).unwrap().remaining, 0);
+
+    // the window starts over once it ran out
+    thread::sleep(Duration::from_millis(250));
+    assert_eq!(limiter.peek("KEYID123", None).unwrap().remaining, 2);
+    assert_eq!(limiter.hit("KEYID123", None).unwrap().ok().unwrap().remaining, 1);
+
+    // 0 is unlimited
+    let unlimited = RequestLimiter::new(0);
+    for _ in 0..100 {
+        assert!(unlimited.hit("KEYID123", None).unwrap().is_ok());
+    }
+    assert!(limiter.hit("KEYID123", Some(0)).unwrap().is_ok());
+}
+
+#[test]
+fn quota_headers() {
+    let rocket = rocket::custom(rocket::Config::development())
+        .mount("/", routes![limited])
+        .manage(RequestLimiter::new(1))
+        .attach(QuotaHeaders);
+    let client = Client::new(rocket).unwrap();
+
+    let response = client.get("/").dispatch();
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("X-RateLimit-Limit"), Some("1"));
+    assert_eq!(response.headers().get_one("X-RateLimit-Remaining"), Some("0"));
+    assert_eq!(response.headers().get_one("Retry-After"), None);
+
+    let response = client.get("/").dispatch();
+    assert_eq!(response.status(), Status::TooManyRequests);
+    let retry_after: u64 = response.headers().get_one("Retry-After").unwrap().parse().unwrap();
+    assert!(retry_after >= 1 && retry_after <= 60);
+}
+
+#[test]
+fn quota_update() {
+    #[derive(Deserialize)]
+    struct Update {
+        #[serde(default, deserialize_with = "crate::server::endpoints::modify_manager::quota_update")]
+        quota: Option<Option<u32>>
+    }
+
+    let parse = |json: &str| serde_json::from_str::<Update>(json).map(|update| update.quota);
+
+    assert_eq!(parse("{}").unwrap(), None);
+    assert_eq!(parse(r#"{"quota": 30}"#).unwrap(), Some(Some(30)));
+    assert_eq!(parse(r#"{"quota": 0}"#).unwrap(), Some(Some(0)));
+    assert_eq!(parse(r#"{"quota": null}"#).unwrap(), Some(None));
+    assert_eq!(parse(r#"{"quota": "reset"}"#).unwrap(), Some(None));
+    assert!(parse(r#"{"quota": "never"}"#).is_err());
+    assert!(parse(r#"{"quota": -1}"#).is_err());
+}